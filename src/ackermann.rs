@@ -0,0 +1,580 @@
+//! Ackermann's reduction from QF_EUF to the quantifier-free theory of equality
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::fol::*;
+use crate::model::*;
+
+/// An application of a symbol, with its arguments after the reduction and its replacement
+type FunctionReplacement = (Rc<FunctionSymbol>, Vec<Rc<Term>>, Rc<Term>);
+type RelationReplacement = (Rc<RelationSymbol>, Vec<Rc<Term>>, Rc<Formula>);
+
+/// The result of replacing every application of a function symbol of positive
+/// arity by a fresh constant and every application of a relation symbol of
+/// positive arity by a fresh nullary relation symbol. Functional consistency is
+/// restored by constraints of the form t1 = s1 /\ ... /\ tn = sn -> c = d,
+/// so the reduced formula is equisatisfiable with the input
+pub struct AckermannReduction {
+    language: Rc<Language>,
+    formula: Rc<Formula>,
+    function_applications: Vec<FunctionReplacement>, // one for each distinct application
+    relation_applications: Vec<RelationReplacement>,
+}
+
+/// Identity of a term, given by its symbol and the identities of its arguments
+#[derive(PartialEq, Eq, Hash)]
+enum TermKey {
+    Variable(Rc<Variable>),
+    Application(Rc<FunctionSymbol>, Vec<usize>),
+}
+
+struct Reducer {
+    used_names: HashSet<String>, // names of the symbols in the language and the fresh symbols
+    next_fresh_index: usize,
+    term_ids: HashMap<TermKey, usize>,
+    reduced_terms: Vec<Rc<Term>>, // replacement of each term
+    relation_ids: HashMap<(Rc<RelationSymbol>, Vec<usize>), usize>,
+    function_applications: Vec<(Rc<FunctionSymbol>, Vec<usize>, usize)>,
+    relation_applications: Vec<(Rc<RelationSymbol>, Vec<usize>, Rc<Formula>)>,
+    fresh_functions: Vec<Rc<FunctionSymbol>>,
+    fresh_relations: Vec<Rc<RelationSymbol>>,
+}
+
+impl Reducer {
+    /// Pick a name of the form <name>#<index> that is not used yet
+    fn fresh_name(&mut self, name: &str) -> String {
+        loop {
+            let fresh = format!("{}#{}", name, self.next_fresh_index);
+            self.next_fresh_index += 1;
+            if self.used_names.insert(fresh.clone()) {
+                return fresh;
+            }
+        }
+    }
+
+    /// Reduce a term and return its identity
+    fn reduce_term(&mut self, term: &Rc<Term>) -> usize {
+        let key = match term.borrow() {
+            Term::Variable(variable) => TermKey::Variable(variable.clone()),
+            Term::Application(symbol, arguments) =>
+                TermKey::Application(symbol.clone(), arguments.iter().map(|argument| self.reduce_term(argument)).collect()),
+        };
+
+        if let Some(id) = self.term_ids.get(&key) {
+            return *id;
+        }
+
+        let id = self.reduced_terms.len();
+        let reduced = match &key {
+            TermKey::Application(symbol, arguments) if !arguments.is_empty() => {
+                let name = self.fresh_name(symbol.get_name());
+                let constant = FunctionSymbol::new(&name, &[], symbol.get_output_sort());
+                self.fresh_functions.push(constant.clone());
+                self.function_applications.push((symbol.clone(), arguments.clone(), id));
+                Term::new_application(&constant, &[])
+            },
+            _ => term.clone(),
+        };
+
+        self.reduced_terms.push(reduced);
+        self.term_ids.insert(key, id);
+        id
+    }
+
+    fn reduce_formula(&mut self, formula: &Rc<Formula>) -> Rc<Formula> {
+        match formula.borrow() {
+            Formula::RelationApplication(symbol, arguments) => {
+                if arguments.is_empty() {
+                    return formula.clone();
+                }
+
+                let arguments = arguments.iter().map(|argument| self.reduce_term(argument)).collect::<Vec<_>>();
+                let key = (symbol.clone(), arguments.clone());
+                if let Some(index) = self.relation_ids.get(&key) {
+                    return self.relation_applications[*index].2.clone();
+                }
+
+                let name = self.fresh_name(symbol.get_name());
+                let relation = RelationSymbol::new(&name, &[]);
+                let atom = Formula::new_relation_application(&relation, &[]);
+                self.fresh_relations.push(relation);
+                self.relation_ids.insert(key, self.relation_applications.len());
+                self.relation_applications.push((symbol.clone(), arguments, atom.clone()));
+                atom
+            },
+            Formula::Equality(left, right) => {
+                let left = self.reduce_term(left);
+                let right = self.reduce_term(right);
+                Formula::new_equality(&self.reduced_terms[left], &self.reduced_terms[right])
+            },
+            Formula::Negation(formula) => Formula::new_negation(&self.reduce_formula(formula)),
+            Formula::Implication(left, right) =>
+                Formula::new_implication(&self.reduce_formula(left), &self.reduce_formula(right)),
+            Formula::Equivalence(left, right) =>
+                Formula::new_equivalence(&self.reduce_formula(left), &self.reduce_formula(right)),
+            Formula::Conjunction(conjuncts) =>
+                Rc::new(Formula::Conjunction(conjuncts.iter().map(|conjunct| self.reduce_formula(conjunct)).collect())),
+            Formula::Disjunction(disjuncts) =>
+                Rc::new(Formula::Disjunction(disjuncts.iter().map(|disjunct| self.reduce_formula(disjunct)).collect())),
+            Formula::UniversalQuantification(..) | Formula::ExistentialQuantification(..) =>
+                unreachable!("quantified formulas are rejected before the reduction"),
+        }
+    }
+
+    /// The conjunction of the equalities between the reduced arguments of two applications
+    fn equal_arguments(&self, arguments1: &[usize], arguments2: &[usize]) -> Rc<Formula> {
+        Rc::new(Formula::Conjunction(
+            arguments1.iter().zip(arguments2)
+                .map(|(argument1, argument2)| Formula::new_equality(&self.reduced_terms[*argument1], &self.reduced_terms[*argument2]))
+                .collect(),
+        ))
+    }
+
+    /// Functional consistency constraints for each pair of applications of the same symbol
+    fn get_constraints(&self) -> Vec<Rc<Formula>> {
+        let mut constraints = vec![];
+
+        for (i, (symbol1, arguments1, id1)) in self.function_applications.iter().enumerate() {
+            for (symbol2, arguments2, id2) in &self.function_applications[..i] {
+                if symbol1 == symbol2 {
+                    constraints.push(Formula::new_implication(
+                        &self.equal_arguments(arguments1, arguments2),
+                        &Formula::new_equality(&self.reduced_terms[*id1], &self.reduced_terms[*id2]),
+                    ));
+                }
+            }
+        }
+
+        for (i, (symbol1, arguments1, atom1)) in self.relation_applications.iter().enumerate() {
+            for (symbol2, arguments2, atom2) in &self.relation_applications[..i] {
+                if symbol1 == symbol2 {
+                    constraints.push(Formula::new_implication(
+                        &self.equal_arguments(arguments1, arguments2),
+                        &Formula::new_equivalence(atom1, atom2),
+                    ));
+                }
+            }
+        }
+
+        constraints
+    }
+}
+
+/// An atom of the reduced formula, with terms identified by their index
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Atom {
+    Equality(usize, usize),
+    Relation(Rc<RelationSymbol>),
+}
+
+/// A decision procedure for formulas whose terms are constants and variables.
+/// It case-splits on the atoms and rejects a partial assignment as soon as a
+/// false equality holds between terms that the true equalities put in one class,
+/// so it shares neither the congruence graph nor the CDCL search with the solver
+struct EqualitySearch {
+    term_ids: HashMap<Rc<Term>, usize>,
+    terms: Vec<Rc<Term>>,
+    atom_ids: HashMap<Atom, usize>,
+    atoms: Vec<Atom>,
+}
+
+impl EqualitySearch {
+    fn new(formula: &Rc<Formula>) -> EqualitySearch {
+        let mut search = EqualitySearch { term_ids: HashMap::new(), terms: vec![], atom_ids: HashMap::new(), atoms: vec![] };
+        search.collect_atoms(formula);
+        search
+    }
+
+    fn get_term_id(&mut self, term: &Rc<Term>) -> usize {
+        if let Some(id) = self.term_ids.get(term) {
+            return *id;
+        }
+
+        let id = self.terms.len();
+        self.terms.push(term.clone());
+        self.term_ids.insert(term.clone(), id);
+        id
+    }
+
+    /// Get the atom of an equality or a relation application, after the atoms are collected
+    fn get_atom(&self, formula: &Formula) -> Atom {
+        match formula {
+            Formula::RelationApplication(symbol, _) => Atom::Relation(symbol.clone()),
+            Formula::Equality(left, right) => Atom::Equality(self.term_ids[left], self.term_ids[right]),
+            _ => unreachable!("not an atom"),
+        }
+    }
+
+    fn collect_atoms(&mut self, formula: &Rc<Formula>) {
+        match formula.borrow() {
+            Formula::RelationApplication(..) | Formula::Equality(..) => {
+                if let Formula::Equality(left, right) = formula.borrow() {
+                    self.get_term_id(left);
+                    self.get_term_id(right);
+                }
+
+                let atom = self.get_atom(formula);
+                if !self.atom_ids.contains_key(&atom) {
+                    self.atom_ids.insert(atom.clone(), self.atoms.len());
+                    self.atoms.push(atom);
+                }
+            },
+            Formula::Negation(formula) => self.collect_atoms(formula),
+            Formula::Implication(left, right) | Formula::Equivalence(left, right) => {
+                self.collect_atoms(left);
+                self.collect_atoms(right);
+            },
+            Formula::Conjunction(formulas) | Formula::Disjunction(formulas) => {
+                for formula in formulas {
+                    self.collect_atoms(formula);
+                }
+            },
+            Formula::UniversalQuantification(..) | Formula::ExistentialQuantification(..) =>
+                unreachable!("the reduced formula is quantifier-free"),
+        }
+    }
+
+    /// Evaluate a formula under a partial assignment of the atoms.
+    /// Returns None if the value depends on the unassigned atoms
+    fn eval(&self, formula: &Rc<Formula>, values: &[Option<bool>]) -> Option<bool> {
+        match formula.borrow() {
+            Formula::RelationApplication(..) | Formula::Equality(..) => values[self.atom_ids[&self.get_atom(formula)]],
+            Formula::Negation(formula) => self.eval(formula, values).map(|value| !value),
+            Formula::Implication(left, right) => match (self.eval(left, values), self.eval(right, values)) {
+                (Some(false), _) | (_, Some(true)) => Some(true),
+                (Some(true), Some(false)) => Some(false),
+                _ => None,
+            },
+            Formula::Equivalence(left, right) => Some(self.eval(left, values)? == self.eval(right, values)?),
+            Formula::Conjunction(conjuncts) => {
+                let values = conjuncts.iter().map(|conjunct| self.eval(conjunct, values)).collect::<Vec<_>>();
+                if values.contains(&Some(false)) {
+                    Some(false)
+                } else {
+                    values.into_iter().collect::<Option<Vec<_>>>().map(|_| true)
+                }
+            },
+            Formula::Disjunction(disjuncts) => {
+                let values = disjuncts.iter().map(|disjunct| self.eval(disjunct, values)).collect::<Vec<_>>();
+                if values.contains(&Some(true)) {
+                    Some(true)
+                } else {
+                    values.into_iter().collect::<Option<Vec<_>>>().map(|_| false)
+                }
+            },
+            Formula::UniversalQuantification(..) | Formula::ExistentialQuantification(..) =>
+                unreachable!("the reduced formula is quantifier-free"),
+        }
+    }
+
+    /// Get the representative of the class of each term, with the classes given by
+    /// the true equalities. Returns None if some false equality is within a class
+    fn get_classes(&self, values: &[Option<bool>]) -> Option<Vec<usize>> {
+        fn find(parents: &mut [usize], mut term: usize) -> usize {
+            while parents[term] != term {
+                parents[term] = parents[parents[term]];
+                term = parents[term];
+            }
+            term
+        }
+
+        let mut parents = (0..self.terms.len()).collect::<Vec<_>>();
+        for (atom, value) in self.atoms.iter().zip(values) {
+            if let (Atom::Equality(left, right), Some(true)) = (atom, value) {
+                let (left, right) = (find(&mut parents, *left), find(&mut parents, *right));
+                parents[left] = right;
+            }
+        }
+
+        for (atom, value) in self.atoms.iter().zip(values) {
+            if let (Atom::Equality(left, right), Some(false)) = (atom, value) {
+                if find(&mut parents, *left) == find(&mut parents, *right) {
+                    return None;
+                }
+            }
+        }
+
+        Some((0..self.terms.len()).map(|term| find(&mut parents, term)).collect())
+    }
+
+    /// Extend the partial assignment to one that makes the formula true, trying
+    /// true before false for each atom. Returns false if there is none
+    fn search(&self, formula: &Rc<Formula>, values: &mut Vec<Option<bool>>) -> bool {
+        if self.get_classes(values).is_none() {
+            return false;
+        }
+
+        match self.eval(formula, values) {
+            Some(value) => value,
+            None => {
+                // some atom is unassigned, since the value is not determined
+                let atom = values.iter().position(Option::is_none).unwrap();
+                for value in [true, false] {
+                    values[atom] = Some(value);
+                    if self.search(formula, values) {
+                        return true;
+                    }
+                }
+                values[atom] = None;
+                false
+            },
+        }
+    }
+}
+
+impl AckermannReduction {
+    /// Reduce a quantifier-free formula over the given language.
+    /// Returns None if the formula has quantifiers
+    pub fn new(language: &Rc<Language>, formula: &Rc<Formula>) -> Option<AckermannReduction> {
+        if formula.has_quantifiers() {
+            return None;
+        }
+
+        let used_names = language.iter_function_symbols().map(|symbol| symbol.get_name())
+            .chain(language.iter_relation_symbols().map(|symbol| symbol.get_name()))
+            .map(|name| name.to_string())
+            .collect();
+        let mut reducer = Reducer {
+            used_names,
+            next_fresh_index: 0,
+            term_ids: HashMap::new(),
+            reduced_terms: vec![],
+            relation_ids: HashMap::new(),
+            function_applications: vec![],
+            relation_applications: vec![],
+            fresh_functions: vec![],
+            fresh_relations: vec![],
+        };
+
+        let reduced = reducer.reduce_formula(formula);
+        let mut conjuncts = vec![reduced];
+        conjuncts.extend(reducer.get_constraints());
+
+        let sorts = language.iter_sorts().collect::<Vec<_>>();
+        let functions = language.iter_function_symbols()
+            .filter(|symbol| symbol.arity() == 0)
+            .chain(reducer.fresh_functions.iter())
+            .collect::<Vec<_>>();
+        let relations = language.iter_relation_symbols()
+            .filter(|symbol| symbol.arity() == 0)
+            .chain(reducer.fresh_relations.iter())
+            .collect::<Vec<_>>();
+
+        let reduced_terms = &reducer.reduced_terms;
+        let get_arguments = |arguments: &[usize]| arguments.iter().map(|argument| reduced_terms[*argument].clone()).collect::<Vec<_>>();
+
+        Some(AckermannReduction {
+            language: Language::new(&sorts, &functions, &relations),
+            formula: Rc::new(Formula::Conjunction(conjuncts)),
+            function_applications: reducer.function_applications.iter()
+                .map(|(symbol, arguments, id)| (symbol.clone(), get_arguments(arguments), reduced_terms[*id].clone()))
+                .collect(),
+            relation_applications: reducer.relation_applications.iter()
+                .map(|(symbol, arguments, atom)| (symbol.clone(), get_arguments(arguments), atom.clone()))
+                .collect(),
+        })
+    }
+
+    /// Get the language of the reduced formula, which only has
+    /// constants and nullary relation symbols
+    pub fn get_language(&self) -> &Rc<Language> {
+        &self.language
+    }
+
+    /// Get the reduced formula, which has no application of positive arity
+    pub fn get_formula(&self) -> &Rc<Formula> {
+        &self.formula
+    }
+
+    /// Decide the reduced formula by case splits on its atoms, without congruence
+    /// closure or the CDCL search, and return a model over the reduced language if it is satisfiable
+    pub fn solve(&self) -> Option<Model> {
+        let search = EqualitySearch::new(&self.formula);
+        let mut values = vec![None; search.atoms.len()];
+        if !search.search(&self.formula, &mut values) {
+            return None;
+        }
+
+        // one element for each class of terms
+        let classes = search.get_classes(&values).unwrap();
+        let mut model = Model::new(&self.language);
+        let mut elements = HashMap::new();
+        for (term, class) in search.terms.iter().zip(classes) {
+            match term.borrow() {
+                Term::Variable(variable) => {
+                    let element = *elements.entry(class).or_insert_with(|| model.add_element(&variable.sort));
+                    model.set_variable_value(variable, element);
+                },
+                Term::Application(symbol, _) => {
+                    let element = *elements.entry(class).or_insert_with(|| model.add_element(symbol.get_output_sort()));
+                    model.set_function_value(symbol, vec![], element);
+                },
+            }
+        }
+
+        // unassigned atoms do not affect the value of the formula
+        for (atom, value) in search.atoms.iter().zip(&values) {
+            if let Atom::Relation(symbol) = atom {
+                model.set_relation_value(symbol, vec![], value.unwrap_or(false));
+            }
+        }
+
+        model.complete();
+        Some(model)
+    }
+
+    /// Translate a model of the reduced formula to a model of the input formula over the given language
+    pub fn translate_model(&self, language: &Rc<Language>, model: &Model) -> Model {
+        let mut translated = Model::new(language);
+        for element in 0..model.get_num_elements() {
+            translated.add_element(model.get_sort(element));
+        }
+
+        let assignment = Assignment::new();
+
+        for (variable, value) in model.get_assignment() {
+            translated.set_variable_value(variable, *value);
+        }
+
+        // constants and nullary relation symbols are not replaced
+        for symbol in language.iter_function_symbols().filter(|symbol| symbol.arity() == 0) {
+            if let Some(value) = model.get_function_value(symbol, &[]) {
+                translated.set_function_value(symbol, vec![], value);
+            }
+        }
+
+        for symbol in language.iter_relation_symbols().filter(|symbol| symbol.arity() == 0) {
+            if let Some(value) = model.get_relation_value(symbol, &[]) {
+                translated.set_relation_value(symbol, vec![], value);
+            }
+        }
+
+        // the constraints make the values of the applications consistent
+        for (symbol, arguments, constant) in &self.function_applications {
+            let arguments = arguments.iter().map(|argument| model.eval_term(argument, &assignment).unwrap()).collect();
+            translated.set_function_value(symbol, arguments, model.eval_term(constant, &assignment).unwrap());
+        }
+
+        for (symbol, arguments, atom) in &self.relation_applications {
+            let arguments = arguments.iter().map(|argument| model.eval_term(argument, &assignment).unwrap()).collect();
+            translated.set_relation_value(symbol, arguments, model.eval_formula(atom, &assignment).unwrap());
+        }
+
+        translated.complete();
+        translated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::{QFEUFSolver, SatResult};
+
+    fn has_applications(formula: &Rc<Formula>) -> bool {
+        fn term_has_applications(term: &Rc<Term>) -> bool {
+            matches!(term.borrow(), Term::Application(_, arguments) if !arguments.is_empty())
+        }
+
+        match formula.borrow() {
+            Formula::RelationApplication(_, arguments) => !arguments.is_empty(),
+            Formula::Equality(left, right) => term_has_applications(left) || term_has_applications(right),
+            Formula::Negation(formula) => has_applications(formula),
+            Formula::Implication(left, right) | Formula::Equivalence(left, right) =>
+                has_applications(left) || has_applications(right),
+            Formula::Conjunction(formulas) | Formula::Disjunction(formulas) => formulas.iter().any(has_applications),
+            Formula::UniversalQuantification(_, body) | Formula::ExistentialQuantification(_, body) => has_applications(body),
+        }
+    }
+
+    #[test]
+    fn reduction_removes_applications() {
+        // f(f(a)) = a /\ P(f(a)) /\ ¬P(a)
+        let sort_a = Sort::new("A");
+        let constant_a = FunctionSymbol::new("a", &[], &sort_a);
+        let function_f = FunctionSymbol::new("f", &[&sort_a], &sort_a);
+        let relation_p = RelationSymbol::new("P", &[&sort_a]);
+        let language = Language::new(&[&sort_a], &[&constant_a, &function_f], &[&relation_p]);
+
+        let a = Term::new_application(&constant_a, &[]);
+        let fa = Term::new_application(&function_f, &[&a]);
+        let ffa = Term::new_application(&function_f, &[&fa]);
+        let formula = Formula::new_conjunction(&[
+            &Formula::new_equality(&ffa, &a),
+            &Formula::new_relation_application(&relation_p, &[&fa]),
+            &Formula::new_negation(&Formula::new_relation_application(&relation_p, &[&a])),
+        ]);
+
+        let reduction = AckermannReduction::new(&language, &formula).unwrap();
+        assert!(!has_applications(reduction.get_formula()));
+
+        // f(a) and f(f(a)) share a constraint, and so do P(f(a)) and P(a)
+        assert_eq!(reduction.get_language().iter_function_symbols().count(), 3);
+        assert_eq!(reduction.get_language().iter_relation_symbols().count(), 2);
+        match reduction.get_formula().borrow() {
+            Formula::Conjunction(conjuncts) => assert_eq!(conjuncts.len(), 3),
+            _ => panic!("expected a conjunction"),
+        }
+    }
+
+    #[test]
+    fn fresh_names_avoid_the_language() {
+        // f(a) != c /\ f(c) = a, where the constant c is named f#0
+        let sort_a = Sort::new("A");
+        let constant_a = FunctionSymbol::new("a", &[], &sort_a);
+        let constant_c = FunctionSymbol::new("f#0", &[], &sort_a);
+        let function_f = FunctionSymbol::new("f", &[&sort_a], &sort_a);
+        let language = Language::new(&[&sort_a], &[&constant_a, &constant_c, &function_f], &[]);
+
+        let a = Term::new_application(&constant_a, &[]);
+        let c = Term::new_application(&constant_c, &[]);
+        let formula = Formula::new_conjunction(&[
+            &Formula::new_negation(&Formula::new_equality(&Term::new_application(&function_f, &[&a]), &c)),
+            &Formula::new_equality(&Term::new_application(&function_f, &[&c]), &a),
+        ]);
+
+        let reduction = AckermannReduction::new(&language, &formula).unwrap();
+        let names = reduction.get_language().iter_function_symbols().map(|symbol| symbol.get_name()).collect::<HashSet<_>>();
+        assert_eq!(names.len(), 4);
+        assert!(matches!(QFEUFSolver::sat_ackermann(&language, &formula), SatResult::Sat(_)));
+
+        let x = Rc::new(Variable { index: 0, sort: sort_a.clone() });
+        let quantified = Rc::new(Formula::UniversalQuantification(
+            x.clone(),
+            Formula::new_equality(&Rc::new(Term::Variable(x)), &a),
+        ));
+        assert!(AckermannReduction::new(&language, &quantified).is_none());
+        assert!(matches!(QFEUFSolver::sat_ackermann(&language, &quantified), SatResult::Unknown));
+    }
+
+    #[test]
+    fn reduced_formula_is_decided_by_case_splits() {
+        // (a = b \/ a = c) /\ f(b) != f(c) /\ f(a) != f(b) is sat only with a = c
+        let sort_a = Sort::new("A");
+        let constants = ["a", "b", "c"].map(|name| FunctionSymbol::new(name, &[], &sort_a));
+        let function_f = FunctionSymbol::new("f", &[&sort_a], &sort_a);
+        let language = Language::new(&[&sort_a], &[&constants[0], &constants[1], &constants[2], &function_f], &[]);
+
+        let [a, b, c] = constants.map(|constant| Term::new_application(&constant, &[]));
+        let [fa, fb, fc] = [&a, &b, &c].map(|term| Term::new_application(&function_f, &[term]));
+        let formula = Formula::new_conjunction(&[
+            &Formula::new_disjunction(&[&Formula::new_equality(&a, &b), &Formula::new_equality(&a, &c)]),
+            &Formula::new_negation(&Formula::new_equality(&fb, &fc)),
+            &Formula::new_negation(&Formula::new_equality(&fa, &fb)),
+        ]);
+
+        let reduction = AckermannReduction::new(&language, &formula).unwrap();
+        let model = reduction.solve().unwrap();
+        assert_eq!(model.eval_formula(reduction.get_formula(), &Assignment::new()), Some(true));
+
+        let model = reduction.translate_model(&language, &model);
+        assert_eq!(model.eval_formula(&formula, &Assignment::new()), Some(true));
+        assert_eq!(model.eval_formula(&Formula::new_equality(&a, &c), &Assignment::new()), Some(true));
+
+        // adding f(a) != f(c) makes it unsat
+        let formula = Formula::new_conjunction(&[&formula, &Formula::new_negation(&Formula::new_equality(&fa, &fc))]);
+        assert!(AckermannReduction::new(&language, &formula).unwrap().solve().is_none());
+    }
+}
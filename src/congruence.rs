@@ -1,29 +1,248 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
 
 pub type NodeIndex = usize;
 pub type SymbolIndex = usize;
 
+/// The signature of a node is its symbol together with
+/// the congruence classes of its children, so two nodes
+/// are congruent iff they have the same signature
+type Signature = (SymbolIndex, Vec<NodeIndex>);
+
+/// Reason for an edge in the proof forest
+#[derive(Clone, Copy, Debug)]
+enum Justification {
+    /// The two nodes were merged by merge_congruence_classes
+    Input(NodeIndex, NodeIndex),
+    /// The two nodes have the same symbol and pairwise equal children
+    Congruence(NodeIndex, NodeIndex),
+}
+
+/// A disequality between two nodes that are in the same congruence class
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub disequality: (NodeIndex, NodeIndex),
+}
+
+/// Union-find over node indices with union by rank and path compression
+struct UnionFind {
+    parent: Vec<Cell<NodeIndex>>, // roots are their own parents
+    rank: Vec<usize>, // upper bound on the height of the tree rooted at a node
+}
+
+impl UnionFind {
+    fn new() -> UnionFind {
+        UnionFind { parent: vec![], rank: vec![] }
+    }
+
+    /// Add a new singleton class
+    fn add(&mut self) -> NodeIndex {
+        let node = self.parent.len();
+        self.parent.push(Cell::new(node));
+        self.rank.push(0);
+        node
+    }
+
+    /// Remove the most recently added class, which must be a singleton
+    fn remove_last(&mut self) {
+        self.parent.pop();
+        self.rank.pop();
+    }
+
+    /// Get the parent of a node, or None if it is a root
+    fn get_parent(&self, node: NodeIndex) -> Option<NodeIndex> {
+        let parent = self.parent[node].get();
+        if parent == node { None } else { Some(parent) }
+    }
+
+    /// Find the root of the tree containing node. If compress is set, all nodes
+    /// on the path are linked directly to the root. Compression cannot be undone,
+    /// so it must not be used on links that may be removed by undo_union later
+    fn find(&self, node: NodeIndex, compress: bool) -> NodeIndex {
+        let mut root = node;
+        while let Some(parent) = self.get_parent(root) {
+            root = parent;
+        }
+
+        if compress {
+            let mut current = node;
+            while current != root {
+                current = self.parent[current].replace(root);
+            }
+        }
+
+        root
+    }
+
+    /// Link two roots, the one with the smaller rank goes under the other.
+    /// Returns (root, child, whether the rank of root has increased)
+    fn union(&mut self, root1: NodeIndex, root2: NodeIndex) -> (NodeIndex, NodeIndex, bool) {
+        debug_assert!(self.get_parent(root1).is_none() && self.get_parent(root2).is_none());
+
+        let (root, child) = if self.rank[root1] < self.rank[root2] { (root2, root1) } else { (root1, root2) };
+        self.parent[child].set(root);
+
+        let rank_increased = self.rank[root] == self.rank[child];
+        if rank_increased {
+            self.rank[root] += 1;
+        }
+
+        (root, child, rank_increased)
+    }
+
+    /// Undo a union returned by the union method
+    fn undo_union(&mut self, root: NodeIndex, child: NodeIndex, rank_increased: bool) {
+        self.parent[child].set(child);
+        if rank_increased {
+            self.rank[root] -= 1;
+        }
+    }
+}
+
+/// A change to the graph that can be undone by pop
+enum TrailEntry {
+    /// A node was appended to the graph
+    AddNode,
+    /// A signature was inserted into the signature table
+    InsertSignature(Signature),
+    /// A disequality was appended to the list of disequalities
+    AddDisequality,
+    /// The class of child was linked under root, which had the given
+    /// number of parents and disequalities before
+    Union { child: NodeIndex, root: NodeIndex, rank_increased: bool, root_parents: usize, root_disequalities: usize },
+    /// The proof parent of a node was overwritten
+    SetProofParent(NodeIndex, Option<(NodeIndex, Justification)>),
+}
+
 struct NodeData {
     symbol: SymbolIndex,
-    cong_class: Option<NodeIndex>,
-    cong_height: usize, // upper bound on the height of the tree formed by cong_class
-    parents: Vec<NodeIndex>,
+    parents: Vec<NodeIndex>, // for a representative, the parents of all nodes in its class
+    disequalities: Vec<usize>, // for a representative, the disequalities involving its class
     children: Vec<NodeIndex>,
+    proof_parent: Option<(NodeIndex, Justification)>, // edge in the proof forest
 }
 
 pub struct CongruenceGraph {
     nodes: Vec<NodeData>,
+    classes: UnionFind,
+    node_index: HashMap<(SymbolIndex, Vec<NodeIndex>), NodeIndex>, // hash-consing of (symbol, children)
+    signatures: HashMap<Signature, NodeIndex>,
+    disequalities: Vec<(NodeIndex, NodeIndex)>,
+    trail: Vec<TrailEntry>,
+    checkpoints: Vec<usize>, // trail length at each push
+}
+
+impl Default for CongruenceGraph {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CongruenceGraph {
     pub fn new() -> CongruenceGraph {
-        CongruenceGraph { nodes: vec![] }
+        CongruenceGraph {
+            nodes: vec![],
+            classes: UnionFind::new(),
+            node_index: HashMap::new(),
+            signatures: HashMap::new(),
+            disequalities: vec![],
+            trail: vec![],
+            checkpoints: vec![],
+        }
     }
 
-    // /// Get the symbol of a node
-    // pub fn get_symbol(&self, node: NodeIndex) -> SymbolIndex {
-    //     self.nodes[node].symbol
-    // }
+    /// Save the current state so that it can be restored by pop
+    pub fn push(&mut self) {
+        self.checkpoints.push(self.trail.len());
+    }
+
+    /// Restore the state saved by the n-th most recent push
+    pub fn pop(&mut self, n: usize) {
+        assert!(n <= self.checkpoints.len(), "cannot pop {} checkpoints, only {} pushed", n, self.checkpoints.len());
+        if n == 0 {
+            return;
+        }
+
+        // keep the checkpoints until the trail is undone, so that
+        // finding classes during undo does not compress paths
+        let trail_len = self.checkpoints[self.checkpoints.len() - n];
+
+        while self.trail.len() > trail_len {
+            match self.trail.pop().unwrap() {
+                TrailEntry::AddNode => {
+                    let node = self.nodes.pop().unwrap();
+                    self.classes.remove_last();
+                    self.node_index.remove(&(node.symbol, node.children.clone()));
+                    for child in &node.children {
+                        let child_class = self.get_congruent_class(*child);
+                        let parent = self.nodes[child_class].parents.pop();
+                        debug_assert_eq!(parent, Some(self.nodes.len()));
+                    }
+                },
+                TrailEntry::InsertSignature(signature) => {
+                    self.signatures.remove(&signature);
+                },
+                TrailEntry::AddDisequality => {
+                    let (node1, node2) = self.disequalities.pop().unwrap();
+                    for node in [node1, node2] {
+                        let class = self.get_congruent_class(node);
+                        let disequality = self.nodes[class].disequalities.pop();
+                        debug_assert_eq!(disequality, Some(self.disequalities.len()));
+                    }
+                },
+                TrailEntry::Union { child, root, rank_increased, root_parents, root_disequalities } => {
+                    self.classes.undo_union(root, child, rank_increased);
+                    self.nodes[root].parents.truncate(root_parents);
+                    self.nodes[root].disequalities.truncate(root_disequalities);
+                },
+                TrailEntry::SetProofParent(node, proof_parent) => {
+                    self.nodes[node].proof_parent = proof_parent;
+                },
+            }
+        }
+
+        self.checkpoints.truncate(self.checkpoints.len() - n);
+    }
+
+    /// Get the number of pushes that have not been popped
+    pub fn get_num_checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Record a change to be undone by pop. Changes made
+    /// when there is no checkpoint can never be undone
+    fn record(&mut self, entry: TrailEntry) {
+        if !self.checkpoints.is_empty() {
+            self.trail.push(entry);
+        }
+    }
+
+    fn insert_signature(&mut self, signature: Signature, node: NodeIndex) {
+        self.signatures.insert(signature.clone(), node);
+        self.record(TrailEntry::InsertSignature(signature));
+    }
+
+    fn set_proof_parent(&mut self, node: NodeIndex, proof_parent: Option<(NodeIndex, Justification)>) {
+        let old_proof_parent = std::mem::replace(&mut self.nodes[node].proof_parent, proof_parent);
+        self.record(TrailEntry::SetProofParent(node, old_proof_parent));
+    }
+
+    /// Get the number of nodes in the graph
+    pub fn get_num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Get the symbol of a node
+    pub fn get_symbol(&self, node: NodeIndex) -> SymbolIndex {
+        self.nodes[node].symbol
+    }
+
+    /// Get the children of a node
+    pub fn get_children(&self, node: NodeIndex) -> &[NodeIndex] {
+        &self.nodes[node].children
+    }
 
     // /// Check if a node has a parent with the given symbol
     // pub fn has_parent_with_symbol(&self, node: NodeIndex, symbol: SymbolIndex) -> bool {
@@ -35,38 +254,62 @@ impl CongruenceGraph {
     //     return false
     // }
 
+    /// Find the node with the given symbol and children, if it has been added
+    pub fn find_node(&self, symbol: SymbolIndex, children: &[NodeIndex]) -> Option<NodeIndex> {
+        self.node_index.get(&(symbol, children.to_vec())).copied()
+    }
+
     /// Add a parent to the given children
     pub fn add_node(&mut self, symbol: SymbolIndex, children: &Vec<NodeIndex>) -> NodeIndex {
         // if there exists a node with the same symbol and children, return that node
-        for (i, node) in self.nodes.iter().enumerate() {
-            if node.symbol == symbol && &node.children == children {
-                return i;
-            }
+        if let Some(node) = self.find_node(symbol, children) {
+            return node;
         }
 
         // check that all children exists
         let new_index = self.nodes.len();
         for child in children {
             debug_assert!(child < &self.nodes.len(), "node {} does not exist", child);
-            self.nodes[*child].parents.push(new_index);
+            let child_class = self.get_congruent_class(*child);
+            self.nodes[child_class].parents.push(new_index);
         }
         self.nodes.push(NodeData {
             symbol,
-            cong_class: None,
-            cong_height: 0,
             parents: vec![],
-            children: children.clone(), 
+            disequalities: vec![],
+            children: children.clone(),
+            proof_parent: None,
         });
-        return new_index;
+        self.classes.add();
+        self.node_index.insert((symbol, children.clone()), new_index);
+        self.record(TrailEntry::AddNode);
+
+        // the new node might be congruent to an existing node
+        // if some of the children have already been merged
+        let signature = self.get_signature(new_index);
+        match self.signatures.get(&signature) {
+            Some(&other) => {
+                // the new node has no parents or disequalities, so this cannot conflict
+                let conflict = self.merge(new_index, other, Justification::Congruence(new_index, other));
+                debug_assert!(conflict.is_ok());
+            },
+            None => self.insert_signature(signature, new_index),
+        }
+
+        new_index
     }
 
     /// Find the representative of the congruence class that node belongs to
     pub fn get_congruent_class(&self, node: NodeIndex) -> NodeIndex {
-        let mut rep = node;
-        while let Some(next_rep) = self.nodes[rep].cong_class {
-            rep = next_rep;
-        }
-        rep
+        // links created after a checkpoint may be undone,
+        // so only compress paths when there is none
+        self.classes.find(node, self.checkpoints.is_empty())
+    }
+
+    /// Get the signature of a node with respect to the current congruence classes
+    fn get_signature(&self, node: NodeIndex) -> Signature {
+        let node = &self.nodes[node];
+        (node.symbol, node.children.iter().map(|child| self.get_congruent_class(*child)).collect())
     }
 
     /// Check if node1 and node2 have exactly the same arguments
@@ -86,12 +329,64 @@ impl CongruenceGraph {
 
         true
     }
-    
-    /// Merge the congruence classes of two nodes
-    pub fn merge_congruence_classes(&mut self, node1: NodeIndex, node2: NodeIndex) {
-        let mut to_be_merged = vec![(node1, node2)];
 
-        while let Some((node1, node2)) = to_be_merged.pop() {
+    /// Record that two nodes are not equal. Returns a conflict
+    /// if they are already in the same congruence class
+    pub fn add_disequality(&mut self, node1: NodeIndex, node2: NodeIndex) -> Result<(), Conflict> {
+        let index = self.disequalities.len();
+        self.disequalities.push((node1, node2));
+        for node in [node1, node2] {
+            let class = self.get_congruent_class(node);
+            self.nodes[class].disequalities.push(index);
+        }
+        self.record(TrailEntry::AddDisequality);
+
+        if self.get_congruent_class(node1) == self.get_congruent_class(node2) {
+            Err(Conflict { disequality: (node1, node2) })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check if the congruence classes of two nodes are known to be distinct
+    pub fn are_disequal(&self, node1: NodeIndex, node2: NodeIndex) -> bool {
+        let node1_class = self.get_congruent_class(node1);
+        let node2_class = self.get_congruent_class(node2);
+        self.find_disequality(node1_class, node2_class).is_some()
+    }
+
+    /// Find a disequality between two congruence classes
+    fn find_disequality(&self, class1: NodeIndex, class2: NodeIndex) -> Option<(NodeIndex, NodeIndex)> {
+        // search through the shorter list
+        let class = if self.nodes[class1].disequalities.len() <= self.nodes[class2].disequalities.len() { class1 } else { class2 };
+
+        for index in &self.nodes[class].disequalities {
+            let (node1, node2) = self.disequalities[*index];
+            let node1_class = self.get_congruent_class(node1);
+            let node2_class = self.get_congruent_class(node2);
+            if (node1_class, node2_class) == (class1, class2) || (node1_class, node2_class) == (class2, class1) {
+                return Some((node1, node2));
+            }
+        }
+
+        None
+    }
+
+    /// Merge the congruence classes of two nodes.
+    /// Returns the first disequality violated by the merge, in which case
+    /// the merge is still completed so that the graph stays closed under
+    /// congruence, and the caller is expected to pop back to a checkpoint
+    pub fn merge_congruence_classes(&mut self, node1: NodeIndex, node2: NodeIndex) -> Result<(), Conflict> {
+        self.merge(node1, node2, Justification::Input(node1, node2))
+    }
+
+    /// Merge the congruence classes of two nodes for the given reason,
+    /// and then merge all pairs of nodes that become congruent
+    fn merge(&mut self, node1: NodeIndex, node2: NodeIndex, justification: Justification) -> Result<(), Conflict> {
+        let mut to_be_merged = vec![(node1, node2, justification)];
+        let mut conflict = None;
+
+        while let Some((node1, node2, justification)) = to_be_merged.pop() {
             let node1_class = self.get_congruent_class(node1);
             let node2_class = self.get_congruent_class(node2);
 
@@ -100,32 +395,192 @@ impl CongruenceGraph {
                 continue
             }
 
-            // make the cong tree more balanced
-            if self.nodes[node1_class].cong_height < self.nodes[node2_class].cong_height {
-                self.nodes[node1].cong_class = Some(node2_class);
-            } else {
-                self.nodes[node2].cong_class = Some(node1_class);
+            if conflict.is_none() {
+                if let Some(disequality) = self.find_disequality(node1_class, node2_class) {
+                    conflict = Some(Conflict { disequality });
+                }
             }
 
-            // TODO: this is a bit slow
-            // need a better way to find
-            // congruent ancestors
-            for i in 0..self.nodes.len() {
-                for j in 0..i {
-                    if self.nodes[i].symbol == self.nodes[j].symbol &&
-                       self.have_congruent_children(i, j) {
-                        to_be_merged.push((i, j));
+            let (root, child, rank_increased) = self.classes.union(node1_class, node2_class);
+            self.record(TrailEntry::Union {
+                child,
+                root,
+                rank_increased,
+                root_parents: self.nodes[root].parents.len(),
+                root_disequalities: self.nodes[root].disequalities.len(),
+            });
+
+            // record the reason in the proof forest, rerooting
+            // the tree of the absorbed class at the merged node
+            let (child_node, root_node) = if node1_class == child { (node1, node2) } else { (node2, node1) };
+            self.make_proof_root(child_node);
+            self.set_proof_parent(child_node, Some((root_node, justification)));
+
+            // only the parents of the absorbed class have changed signatures,
+            // so they are the only candidates for new congruent pairs
+            let child_parents = self.nodes[child].parents.clone();
+            for parent in &child_parents {
+                let signature = self.get_signature(*parent);
+                match self.signatures.get(&signature) {
+                    Some(&other) => {
+                        if self.get_congruent_class(other) != self.get_congruent_class(*parent) {
+                            to_be_merged.push((*parent, other, Justification::Congruence(*parent, other)));
+                        }
                     }
+                    None => self.insert_signature(signature, *parent),
                 }
             }
+
+            self.nodes[root].parents.extend(child_parents);
+
+            let child_disequalities = self.nodes[child].disequalities.clone();
+            self.nodes[root].disequalities.extend(child_disequalities);
+        }
+
+        match conflict {
+            Some(conflict) => Err(conflict),
+            None => Ok(()),
         }
     }
+
+    /// Reverse the path from a node to the root of its proof tree
+    /// so that the node becomes the root
+    fn make_proof_root(&mut self, node: NodeIndex) {
+        let mut current = node;
+        let mut previous = None;
+        while let Some((parent, justification)) = self.nodes[current].proof_parent {
+            self.set_proof_parent(current, previous);
+            previous = Some((current, justification));
+            current = parent;
+        }
+        self.set_proof_parent(current, previous);
+    }
+
+    /// Find the closest common ancestor of two nodes in the proof forest
+    fn get_common_proof_ancestor(&self, node1: NodeIndex, node2: NodeIndex) -> NodeIndex {
+        let mut ancestors = HashSet::new();
+        let mut current = node1;
+        ancestors.insert(current);
+        while let Some((parent, _)) = self.nodes[current].proof_parent {
+            ancestors.insert(parent);
+            current = parent;
+        }
+
+        let mut current = node2;
+        while !ancestors.contains(&current) {
+            current = self.nodes[current].proof_parent.expect("nodes are not in the same proof tree").0;
+        }
+        current
+    }
+
+    /// Collect the input equalities on the proof forest paths between node1 and node2,
+    /// recursively explaining the children of congruence steps.
+    /// Returns None if the nodes are not in the same congruence class.
+    /// The result is sufficient for node1 = node2 but not necessarily minimal
+    pub fn proof_explanation(&self, node1: NodeIndex, node2: NodeIndex) -> Option<Vec<(NodeIndex, NodeIndex)>> {
+        if self.get_congruent_class(node1) != self.get_congruent_class(node2) {
+            return None;
+        }
+
+        let mut explanation = vec![];
+        let mut explained_edges = HashSet::new(); // edges are identified by their lower end
+        let mut to_be_explained = vec![(node1, node2)];
+
+        while let Some((node1, node2)) = to_be_explained.pop() {
+            let ancestor = self.get_common_proof_ancestor(node1, node2);
+
+            for start in [node1, node2] {
+                let mut current = start;
+                while current != ancestor {
+                    let (parent, justification) = self.nodes[current].proof_parent.unwrap();
+                    if explained_edges.insert(current) {
+                        match justification {
+                            Justification::Input(left, right) => explanation.push((left, right)),
+                            Justification::Congruence(left, right) => {
+                                let left_children = &self.nodes[left].children;
+                                let right_children = &self.nodes[right].children;
+                                to_be_explained.extend(left_children.iter().copied().zip(right_children.iter().copied()));
+                            }
+                        }
+                    }
+                    current = parent;
+                }
+            }
+        }
+
+        Some(explanation)
+    }
+
+    /// Explain why node1 and node2 are equal: return a minimal set of the equalities
+    /// given to merge_congruence_classes that implies node1 = node2, i.e. removing
+    /// any of them would no longer imply the equality.
+    /// Returns None if the nodes are not in the same congruence class
+    pub fn explain(&self, node1: NodeIndex, node2: NodeIndex) -> Option<Vec<(NodeIndex, NodeIndex)>> {
+        let explanation = self.proof_explanation(node1, node2)?;
+
+        // congruence closure is complete on any set of nodes closed under children,
+        // so the candidates are checked on a copy of the subterms of the equalities
+        // and of node1 and node2 instead of the whole graph
+        let mut nodes = vec![node1, node2];
+        nodes.extend(explanation.iter().flat_map(|(left, right)| [*left, *right]));
+        let (mut graph, copies) = self.copy_subterms(&nodes);
+        let (copy1, copy2) = (copies[&node1], copies[&node2]);
+
+        // shrink the explanation by deleting one equality at a time: the copy
+        // holds the equalities kept so far, and the ones not yet decided are
+        // merged under a checkpoint to check if node1 = node2 holds without the candidate.
+        // The copy has no disequalities, so merges cannot conflict
+        let mut minimal = vec![];
+        for (i, (left, right)) in explanation.iter().enumerate() {
+            if graph.get_congruent_class(copy1) == graph.get_congruent_class(copy2) {
+                break;
+            }
+
+            graph.push();
+            for (other_left, other_right) in &explanation[i + 1..] {
+                let _ = graph.merge_congruence_classes(copies[other_left], copies[other_right]);
+            }
+            let redundant = graph.get_congruent_class(copy1) == graph.get_congruent_class(copy2);
+            graph.pop(1);
+
+            if !redundant {
+                let _ = graph.merge_congruence_classes(copies[left], copies[right]);
+                minimal.push((*left, *right));
+            }
+        }
+
+        Some(minimal)
+    }
+
+    /// Create a graph with the given nodes and their descendants but no equalities.
+    /// Returns the graph and the index of the copy of each node
+    fn copy_subterms(&self, nodes: &[NodeIndex]) -> (CongruenceGraph, HashMap<NodeIndex, NodeIndex>) {
+        let mut subterms = HashSet::new();
+        let mut to_be_visited = nodes.to_vec();
+        while let Some(node) = to_be_visited.pop() {
+            if subterms.insert(node) {
+                to_be_visited.extend(&self.nodes[node].children);
+            }
+        }
+
+        // children are added before their parents, so they have smaller indices
+        let mut subterms = subterms.into_iter().collect::<Vec<_>>();
+        subterms.sort_unstable();
+
+        let mut graph = CongruenceGraph::new();
+        let mut copies = HashMap::new();
+        for node in subterms {
+            let children = self.nodes[node].children.iter().map(|child| copies[child]).collect();
+            copies.insert(node, graph.add_node(self.nodes[node].symbol, &children));
+        }
+        (graph, copies)
+    }
 }
 
 impl fmt::Display for CongruenceGraph {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for (i, node) in self.nodes.iter().enumerate() {
-            write!(f, "{}: symbol={}, cong={:?}/{}", i, node.symbol, node.cong_class, self.get_congruent_class(i))?;
+            write!(f, "{}: symbol={}, cong={:?}/{}", i, node.symbol, self.classes.get_parent(i), self.get_congruent_class(i))?;
 
             if !node.parents.is_empty() {
                 write!(f, ", parent:")?;
@@ -142,9 +597,243 @@ impl fmt::Display for CongruenceGraph {
             }
 
             if i + 1 < self.nodes.len() {
-                writeln!(f, "")?;
+                writeln!(f)?;
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Add the nodes a, f(a), f(f(a)), ..., f^n(a) with a = 0 and f = 1
+    fn add_chain(graph: &mut CongruenceGraph, n: usize) -> Vec<NodeIndex> {
+        let mut nodes = vec![graph.add_node(0, &vec![])];
+        for i in 0..n {
+            nodes.push(graph.add_node(1, &vec![nodes[i]]));
+        }
+        nodes
+    }
+
+    /// Get the number of links from a node to its representative
+    fn get_depth(graph: &CongruenceGraph, node: NodeIndex) -> usize {
+        let mut depth = 0;
+        let mut current = node;
+        while let Some(parent) = graph.classes.get_parent(current) {
+            current = parent;
+            depth += 1;
+        }
+        depth
+    }
+
+    #[test]
+    fn merge_non_representatives() {
+        let mut graph = CongruenceGraph::new();
+        let nodes = (0..4).map(|i| graph.add_node(i, &vec![])).collect::<Vec<_>>();
+
+        graph.merge_congruence_classes(nodes[0], nodes[1]).unwrap();
+        graph.merge_congruence_classes(nodes[2], nodes[3]).unwrap();
+
+        // at least one node of each pair is not a representative,
+        // merging it must not detach it from its previous class
+        graph.merge_congruence_classes(nodes[1], nodes[3]).unwrap();
+        graph.merge_congruence_classes(nodes[0], nodes[2]).unwrap();
+
+        for node in &nodes {
+            assert_eq!(graph.get_congruent_class(*node), graph.get_congruent_class(nodes[0]));
+        }
+    }
+
+    #[test]
+    fn merge_keeps_other_classes_apart() {
+        let mut graph = CongruenceGraph::new();
+        let nodes = (0..6).map(|i| graph.add_node(i, &vec![])).collect::<Vec<_>>();
+
+        graph.merge_congruence_classes(nodes[0], nodes[1]).unwrap();
+        graph.merge_congruence_classes(nodes[2], nodes[3]).unwrap();
+        graph.merge_congruence_classes(nodes[4], nodes[5]).unwrap();
+        graph.merge_congruence_classes(nodes[1], nodes[2]).unwrap();
+
+        assert_eq!(graph.get_congruent_class(nodes[0]), graph.get_congruent_class(nodes[3]));
+        assert_ne!(graph.get_congruent_class(nodes[0]), graph.get_congruent_class(nodes[4]));
+        assert_ne!(graph.get_congruent_class(nodes[3]), graph.get_congruent_class(nodes[5]));
+    }
+
+    #[test]
+    fn union_by_rank_bounds_depth() {
+        let mut graph = CongruenceGraph::new();
+        let nodes = (0..64).map(|i| graph.add_node(i, &vec![])).collect::<Vec<_>>();
+
+        // always merge through the last node, which would
+        // build a linear chain without balancing
+        graph.push();
+        for i in 1..nodes.len() {
+            graph.merge_congruence_classes(nodes[i], nodes[i - 1]).unwrap();
+        }
+
+        for node in &nodes {
+            assert!(get_depth(&graph, *node) <= 6);
+        }
+    }
+
+    #[test]
+    fn path_compression() {
+        let mut graph = CongruenceGraph::new();
+        let nodes = (0..16).map(|i| graph.add_node(i, &vec![])).collect::<Vec<_>>();
+
+        // pair up nodes in rounds to build a tree of height 4
+        let mut step = 1;
+        while step < nodes.len() {
+            for i in (0..nodes.len()).step_by(2 * step) {
+                graph.merge_congruence_classes(nodes[i], nodes[i + step]).unwrap();
+            }
+            step *= 2;
+        }
+
+        let rep = graph.get_congruent_class(nodes[0]);
+        for node in &nodes {
+            assert_eq!(graph.get_congruent_class(*node), rep);
+            assert!(get_depth(&graph, *node) <= 1);
+        }
+    }
+
+    #[test]
+    fn congruence_after_merge() {
+        let mut graph = CongruenceGraph::new();
+        let a = graph.add_node(0, &vec![]);
+        let b = graph.add_node(1, &vec![]);
+        let fa = graph.add_node(2, &vec![a]);
+
+        graph.merge_congruence_classes(a, b).unwrap();
+
+        // f(b) is added after a = b
+        let fb = graph.add_node(2, &vec![b]);
+        assert_eq!(graph.get_congruent_class(fa), graph.get_congruent_class(fb));
+    }
+
+    #[test]
+    fn congruence_of_chains() {
+        // f(f(f(a))) = a /\ f(f(f(f(f(a))))) = a implies f(a) = a
+        let mut graph = CongruenceGraph::new();
+        let nodes = add_chain(&mut graph, 5);
+
+        graph.merge_congruence_classes(nodes[3], nodes[0]).unwrap();
+        assert_ne!(graph.get_congruent_class(nodes[1]), graph.get_congruent_class(nodes[0]));
+
+        graph.merge_congruence_classes(nodes[5], nodes[0]).unwrap();
+        for node in &nodes {
+            assert_eq!(graph.get_congruent_class(*node), graph.get_congruent_class(nodes[0]));
+        }
+    }
+
+    #[test]
+    fn pop_restores_classes() {
+        let mut graph = CongruenceGraph::new();
+        let nodes = add_chain(&mut graph, 5);
+        let b = graph.add_node(2, &vec![]);
+        let before_push = graph.to_string();
+
+        graph.push();
+        graph.merge_congruence_classes(nodes[3], nodes[0]).unwrap();
+        let after_first_merge = graph.to_string();
+
+        graph.push();
+        let fb = graph.add_node(1, &vec![b]);
+        graph.merge_congruence_classes(nodes[4], b).unwrap();
+        graph.merge_congruence_classes(nodes[5], nodes[0]).unwrap();
+        assert_eq!(graph.get_congruent_class(fb), graph.get_congruent_class(nodes[0]));
+
+        graph.pop(1);
+        assert_eq!(graph.to_string(), after_first_merge);
+        assert_eq!(graph.find_node(1, &[b]), None);
+
+        graph.pop(1);
+        assert_eq!(graph.to_string(), before_push);
+        assert_ne!(graph.get_congruent_class(nodes[3]), graph.get_congruent_class(nodes[0]));
+    }
+
+    #[test]
+    fn explain_is_minimal() {
+        let mut graph = CongruenceGraph::new();
+        let a = graph.add_node(0, &vec![]);
+        let b = graph.add_node(1, &vec![]);
+        let c = graph.add_node(2, &vec![]);
+        let d = graph.add_node(3, &vec![]);
+        let fa = graph.add_node(4, &vec![a]);
+        let fc = graph.add_node(4, &vec![c]);
+        let e = graph.add_node(5, &vec![]);
+
+        graph.merge_congruence_classes(a, d).unwrap();
+        graph.merge_congruence_classes(a, b).unwrap();
+        graph.merge_congruence_classes(b, c).unwrap();
+        graph.merge_congruence_classes(d, c).unwrap();
+
+        let explanation = graph.explain(fa, fc).unwrap();
+        assert_eq!(explanation.len(), 2);
+        assert_eq!(graph.explain(a, e), None);
+    }
+
+    #[test]
+    fn pop_after_merges_without_checkpoint() {
+        // merges before the first push are permanent and their
+        // paths may be compressed, but undoing later changes must
+        // not compress paths through links that are being removed
+        let mut graph = CongruenceGraph::new();
+        let nodes = add_chain(&mut graph, 3);
+        let b = graph.add_node(2, &vec![]);
+        let c = graph.add_node(3, &vec![]);
+        let d = graph.add_node(4, &vec![]);
+
+        graph.merge_congruence_classes(nodes[1], b).unwrap();
+        graph.merge_congruence_classes(nodes[2], c).unwrap();
+
+        graph.push();
+        graph.merge_congruence_classes(nodes[0], nodes[1]).unwrap();
+        graph.add_disequality(c, d).unwrap();
+        graph.pop(1);
+
+        assert_eq!(graph.get_congruent_class(nodes[1]), graph.get_congruent_class(b));
+        assert_eq!(graph.get_congruent_class(nodes[2]), graph.get_congruent_class(c));
+        assert_ne!(graph.get_congruent_class(nodes[0]), graph.get_congruent_class(b));
+        assert!(!graph.are_disequal(c, d));
+        assert!(graph.proof_explanation(nodes[2], c).is_some());
+    }
+
+    #[test]
+    fn conflict_on_congruence() {
+        // f(f(f(a))) = a /\ f(a) != a /\ f(f(f(f(f(a))))) = a is unsat
+        let mut graph = CongruenceGraph::new();
+        let nodes = add_chain(&mut graph, 5);
+
+        graph.merge_congruence_classes(nodes[3], nodes[0]).unwrap();
+        graph.add_disequality(nodes[1], nodes[0]).unwrap();
+        assert!(graph.are_disequal(nodes[4], nodes[3]));
+
+        let conflict = graph.merge_congruence_classes(nodes[5], nodes[0]).unwrap_err();
+        assert_eq!(conflict.disequality, (nodes[1], nodes[0]));
+        assert_eq!(graph.explain(nodes[1], nodes[0]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn pop_restores_disequalities() {
+        let mut graph = CongruenceGraph::new();
+        let a = graph.add_node(0, &vec![]);
+        let b = graph.add_node(1, &vec![]);
+        let c = graph.add_node(2, &vec![]);
+
+        graph.add_disequality(a, b).unwrap();
+
+        graph.push();
+        graph.merge_congruence_classes(b, c).unwrap();
+        graph.add_disequality(a, c).unwrap();
+        assert_eq!(graph.add_disequality(b, c), Err(Conflict { disequality: (b, c) }));
+        assert!(graph.merge_congruence_classes(a, c).is_err());
+
+        graph.pop(1);
+        assert!(graph.are_disequal(a, b));
+        assert!(!graph.are_disequal(a, c));
+        assert!(graph.merge_congruence_classes(a, c).is_ok());
+    }
+}
@@ -1,6 +1,6 @@
-use std::clone;
-/// Syntax of first-order logic
+//! Syntax of first-order logic
 
+use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::hash::Hasher;
@@ -42,13 +42,13 @@ pub struct Variable {
     pub sort: Rc<Sort>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Term {
     Variable(Rc<Variable>),
     Application(Rc<FunctionSymbol>, Vec<Rc<Term>>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Formula {
     RelationApplication(Rc<RelationSymbol>, Vec<Rc<Term>>),
     Equality(Rc<Term>, Rc<Term>),
@@ -61,18 +61,50 @@ pub enum Formula {
     ExistentialQuantification(Rc<Variable>, Rc<Formula>),
 }
 
-fn clone_vec_rc<T>(vec: &[&Rc<T>]) -> Vec<Rc<T>> {
-    vec.iter().map(|elem| (*elem).clone()).collect::<Vec<_>>()
+/// A violation of the sorts or the symbols of a language, with the offending subterm
+#[derive(Debug)]
+pub enum SortError {
+    /// A function symbol applied to the wrong number of arguments
+    FunctionArity { term: Rc<Term>, expected: usize, found: usize },
+    /// A relation symbol applied to the wrong number of arguments
+    RelationArity { formula: Rc<Formula>, expected: usize, found: usize },
+    /// An argument of a function application that does not have the input sort of the symbol
+    FunctionArgument { term: Rc<Term>, position: usize, expected: Rc<Sort>, found: Rc<Sort> },
+    /// An argument of a relation application that does not have the input sort of the symbol
+    RelationArgument { formula: Rc<Formula>, position: usize, expected: Rc<Sort>, found: Rc<Sort> },
+    /// An equality between terms of different sorts
+    Equality { formula: Rc<Formula>, left: Rc<Sort>, right: Rc<Sort> },
+    /// A function symbol that is not in the language
+    UnknownFunction { term: Rc<Term> },
+    /// A relation symbol that is not in the language
+    UnknownRelation { formula: Rc<Formula> },
+    /// A variable of a sort that is not in the language
+    UnknownSort { variable: Rc<Variable> },
 }
 
-fn merge_hash_maps<K: Eq + Hash, V>(map1: HashMap<K, V>, map2: HashMap<K, V>) -> HashMap<K, V> {
-    map1.into_iter().chain(map2).collect()
+/// A reason why formulas cannot be written as an SMT-LIB 2 script that reads back to them
+#[derive(Debug)]
+pub enum SmtLibPrintError {
+    /// A formula with quantifiers, which are not in the logic QF_UF
+    Quantifier { formula: Rc<Formula> },
+    /// A formula with a free variable, which would read back as a constant
+    FreeVariable { formula: Rc<Formula>, variable: Rc<Variable> },
+    /// A sort or symbol whose name contains `|` or `\`, which cannot be quoted
+    Name { name: String },
+}
+
+fn clone_vec_rc<T>(vec: &[&Rc<T>]) -> Vec<Rc<T>> {
+    vec.iter().map(|elem| (*elem).clone()).collect::<Vec<_>>()
 }
 
 impl Sort {
     pub fn new(name: &str) -> Rc<Sort> {
         Rc::new(Sort { name: name.to_string() })
     }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
 }
 
 impl PartialEq for Sort {
@@ -96,6 +128,33 @@ impl RelationSymbol {
             input_sorts: clone_vec_rc(input_sorts),
         })
     }
+
+    pub fn arity(&self) -> usize {
+        self.input_sorts.len()
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_input_sorts(&self) -> &[Rc<Sort>] {
+        &self.input_sorts
+    }
+}
+
+impl PartialEq for RelationSymbol {
+    fn eq(&self, other: &RelationSymbol) -> bool {
+        self.name == other.name && self.input_sorts == other.input_sorts
+    }
+}
+
+impl Eq for RelationSymbol {}
+
+impl Hash for RelationSymbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.input_sorts.hash(state);
+    }
 }
 
 impl FunctionSymbol {
@@ -110,24 +169,38 @@ impl FunctionSymbol {
     pub fn arity(&self) -> usize {
         self.input_sorts.len()
     }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_input_sorts(&self) -> &[Rc<Sort>] {
+        &self.input_sorts
+    }
+
+    pub fn get_output_sort(&self) -> &Rc<Sort> {
+        &self.output_sort
+    }
 }
 
 impl PartialEq for FunctionSymbol {
     fn eq(&self, other: &FunctionSymbol) -> bool {
-        if self.name != other.name {
-            return false;
-        }
-        for (input_sort1, input_sort2) in self.input_sorts.iter().zip(&other.input_sorts) {
-            if input_sort1 != input_sort2 {
-                return false;
-            }
-        }
-        return self.output_sort == other.output_sort;
+        self.name == other.name &&
+        self.input_sorts == other.input_sorts &&
+        self.output_sort == other.output_sort
     }
 }
 
 impl Eq for FunctionSymbol {}
 
+impl Hash for FunctionSymbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.input_sorts.hash(state);
+        self.output_sort.hash(state);
+    }
+}
+
 impl Language {
     pub fn new(
         sorts: &[&Rc<Sort>],
@@ -141,8 +214,187 @@ impl Language {
         })
     }
 
-    pub fn iter_function_symbols(&self) -> Iter<Rc<FunctionSymbol>> {
-        return self.function_symbols.iter();
+    pub fn iter_sorts(&self) -> Iter<'_, Rc<Sort>> {
+        self.sorts.iter()
+    }
+
+    pub fn iter_function_symbols(&self) -> Iter<'_, Rc<FunctionSymbol>> {
+        self.function_symbols.iter()
+    }
+
+    pub fn iter_relation_symbols(&self) -> Iter<'_, Rc<RelationSymbol>> {
+        self.relation_symbols.iter()
+    }
+
+    /// Get a prefix for the names of variables in SMT-LIB 2 (see `SmtLib`), such that
+    /// no function or relation symbol is named like a variable
+    pub fn get_smtlib_variable_prefix(&self) -> String {
+        let names = self.function_symbols.iter().map(|symbol| &symbol.name)
+            .chain(self.relation_symbols.iter().map(|symbol| &symbol.name))
+            .collect::<Vec<_>>();
+        let mut prefix = "x".to_string();
+        while names.iter().any(|name| {
+            name.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        }) {
+            prefix.push('_');
+        }
+        prefix
+    }
+
+    /// Write an SMT-LIB 2 script in the logic QF_UF that declares the language, asserts
+    /// the formulas and checks their satisfiability. Relation symbols are declared as
+    /// functions to Bool. The script reads back to the same language and formulas, so
+    /// formulas with quantifiers or free variables and names that cannot be quoted are rejected
+    pub fn to_smtlib_script(&self, formulas: &[Rc<Formula>]) -> Result<String, SmtLibPrintError> {
+        let names = self.sorts.iter().map(|sort| &sort.name)
+            .chain(self.function_symbols.iter().map(|symbol| &symbol.name))
+            .chain(self.relation_symbols.iter().map(|symbol| &symbol.name));
+        for name in names {
+            if name.contains(['|', '\\']) {
+                return Err(SmtLibPrintError::Name { name: name.clone() });
+            }
+        }
+
+        for formula in formulas {
+            if formula.has_quantifiers() {
+                return Err(SmtLibPrintError::Quantifier { formula: formula.clone() });
+            }
+            let free_variables = formula.get_free_variables();
+            if let Some(variable) = free_variables.iter().min_by_key(|variable| (variable.index, &variable.sort.name)) {
+                return Err(SmtLibPrintError::FreeVariable { formula: formula.clone(), variable: variable.clone() });
+            }
+        }
+
+        let mut script = String::new();
+        script.push_str("(set-logic QF_UF)\n");
+
+        for sort in &self.sorts {
+            script.push_str(&format!("(declare-sort {} 0)\n", SmtLib::new(sort.as_ref())));
+        }
+
+        let write_sorts = |sorts: &[Rc<Sort>]| sorts.iter()
+            .map(|sort| SmtLib::new(sort.as_ref()).to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        for symbol in &self.function_symbols {
+            script.push_str(&format!(
+                "(declare-fun {} ({}) {})\n",
+                smtlib_symbol(&symbol.name), write_sorts(&symbol.input_sorts), SmtLib::new(symbol.output_sort.as_ref()),
+            ));
+        }
+
+        for symbol in &self.relation_symbols {
+            script.push_str(&format!("(declare-fun {} ({}) Bool)\n", smtlib_symbol(&symbol.name), write_sorts(&symbol.input_sorts)));
+        }
+
+        for formula in formulas {
+            script.push_str(&format!("(assert {})\n", SmtLib::new(formula.as_ref())));
+        }
+        script.push_str("(check-sat)\n");
+        Ok(script)
+    }
+
+    /// Check that a term only uses symbols and sorts of the language, and that
+    /// every application has arguments of the input sorts of its symbol
+    pub fn check_term(&self, term: &Rc<Term>) -> Vec<SortError> {
+        let mut errors = vec![];
+        self.collect_term_errors(term, &mut errors);
+        errors
+    }
+
+    /// Check that a formula only uses symbols and sorts of the language, that every
+    /// application has arguments of the input sorts of its symbol, and that both
+    /// sides of every equality have the same sort
+    pub fn check_formula(&self, formula: &Rc<Formula>) -> Vec<SortError> {
+        let mut errors = vec![];
+        self.collect_formula_errors(formula, &mut errors);
+        errors
+    }
+
+    fn collect_variable_errors(&self, variable: &Rc<Variable>, errors: &mut Vec<SortError>) {
+        if !self.sorts.contains(&variable.sort) {
+            errors.push(SortError::UnknownSort { variable: variable.clone() });
+        }
+    }
+
+    fn collect_term_errors(&self, term: &Rc<Term>, errors: &mut Vec<SortError>) {
+        match term.borrow() {
+            Term::Variable(variable) => self.collect_variable_errors(variable, errors),
+            Term::Application(symbol, arguments) => {
+                for argument in arguments {
+                    self.collect_term_errors(argument, errors);
+                }
+
+                if !self.function_symbols.contains(symbol) {
+                    errors.push(SortError::UnknownFunction { term: term.clone() });
+                }
+
+                if symbol.arity() != arguments.len() {
+                    errors.push(SortError::FunctionArity { term: term.clone(), expected: symbol.arity(), found: arguments.len() });
+                    return;
+                }
+
+                for (position, (argument, expected)) in arguments.iter().zip(symbol.get_input_sorts()).enumerate() {
+                    let found = argument.get_sort();
+                    if found != expected {
+                        errors.push(SortError::FunctionArgument {
+                            term: term.clone(), position, expected: expected.clone(), found: found.clone(),
+                        });
+                    }
+                }
+            },
+        }
+    }
+
+    fn collect_formula_errors(&self, formula: &Rc<Formula>, errors: &mut Vec<SortError>) {
+        match formula.borrow() {
+            Formula::RelationApplication(symbol, arguments) => {
+                for argument in arguments {
+                    self.collect_term_errors(argument, errors);
+                }
+
+                if !self.relation_symbols.contains(symbol) {
+                    errors.push(SortError::UnknownRelation { formula: formula.clone() });
+                }
+
+                if symbol.arity() != arguments.len() {
+                    errors.push(SortError::RelationArity { formula: formula.clone(), expected: symbol.arity(), found: arguments.len() });
+                    return;
+                }
+
+                for (position, (argument, expected)) in arguments.iter().zip(symbol.get_input_sorts()).enumerate() {
+                    let found = argument.get_sort();
+                    if found != expected {
+                        errors.push(SortError::RelationArgument {
+                            formula: formula.clone(), position, expected: expected.clone(), found: found.clone(),
+                        });
+                    }
+                }
+            },
+            Formula::Equality(left, right) => {
+                self.collect_term_errors(left, errors);
+                self.collect_term_errors(right, errors);
+                if left.get_sort() != right.get_sort() {
+                    errors.push(SortError::Equality {
+                        formula: formula.clone(), left: left.get_sort().clone(), right: right.get_sort().clone(),
+                    });
+                }
+            },
+            Formula::Negation(formula) => self.collect_formula_errors(formula, errors),
+            Formula::Implication(left, right) | Formula::Equivalence(left, right) => {
+                self.collect_formula_errors(left, errors);
+                self.collect_formula_errors(right, errors);
+            },
+            Formula::Conjunction(formulas) | Formula::Disjunction(formulas) => {
+                for formula in formulas {
+                    self.collect_formula_errors(formula, errors);
+                }
+            },
+            Formula::UniversalQuantification(variable, body) | Formula::ExistentialQuantification(variable, body) => {
+                self.collect_variable_errors(variable, errors);
+                self.collect_formula_errors(body, errors);
+            },
+        }
     }
 }
 
@@ -163,6 +415,24 @@ impl Hash for Variable {
 
 pub type VariableSet = HashSet<Rc<Variable>>;
 
+/// A map from variables to the terms replacing them
+pub type Substitution = HashMap<Rc<Variable>, Rc<Term>>;
+
+impl Variable {
+    /// Create a variable of the given sort that is not in the set,
+    /// with the smallest index among such variables
+    pub fn new_fresh(sort: &Rc<Sort>, avoid: &VariableSet) -> Rc<Variable> {
+        let mut index = 0;
+        loop {
+            let variable = Rc::new(Variable { index, sort: sort.clone() });
+            if !avoid.contains(&variable) {
+                return variable;
+            }
+            index += 1;
+        }
+    }
+}
+
 impl Term {
     pub fn new_variable(index: VariableIndex, sort: &Rc<Sort>) -> Rc<Term> {
         Rc::new(Term::Variable(Rc::new(Variable { index, sort: sort.clone() })))
@@ -172,6 +442,15 @@ impl Term {
         Rc::new(Term::Application(symbol.clone(), clone_vec_rc(arguments)))
     }
 
+    /// Get the sort of a term, which is determined by its
+    /// variable or the output sort of its function symbol
+    pub fn get_sort(&self) -> &Rc<Sort> {
+        match self {
+            Term::Variable(variable) => &variable.sort,
+            Term::Application(symbol, _) => symbol.get_output_sort(),
+        }
+    }
+
     pub fn collect_free_variables_in_set(&self, free_vars: &mut VariableSet) {
         match self {
             Term::Variable(variable) => {
@@ -190,6 +469,20 @@ impl Term {
         self.collect_free_variables_in_set(&mut free_vars);
         free_vars
     }
+
+    /// Replace the variables in the domain of the substitution
+    pub fn substitute(term: &Rc<Term>, substitution: &Substitution) -> Rc<Term> {
+        match term.borrow() {
+            Term::Variable(variable) => match substitution.get(variable) {
+                Some(replacement) => replacement.clone(),
+                None => term.clone(),
+            },
+            Term::Application(symbol, arguments) => Rc::new(Term::Application(
+                symbol.clone(),
+                arguments.iter().map(|argument| Term::substitute(argument, substitution)).collect(),
+            )),
+        }
+    }
 }
 
 impl Formula {
@@ -281,6 +574,258 @@ impl Formula {
         self.collect_free_variables_in_set(&mut free_vars);
         free_vars
     }
+
+    /// Check if the formula contains a quantifier
+    pub fn has_quantifiers(&self) -> bool {
+        match self {
+            Formula::RelationApplication(..) | Formula::Equality(..) => false,
+            Formula::Negation(formula) => formula.has_quantifiers(),
+            Formula::Implication(left, right) | Formula::Equivalence(left, right) => left.has_quantifiers() || right.has_quantifiers(),
+            Formula::Conjunction(formulas) | Formula::Disjunction(formulas) => formulas.iter().any(|formula| formula.has_quantifiers()),
+            Formula::UniversalQuantification(..) | Formula::ExistentialQuantification(..) => true,
+        }
+    }
+
+    /// Replace the free variables in the domain of the substitution. Bound
+    /// variables are renamed when a replacing term would be captured otherwise
+    pub fn substitute(formula: &Rc<Formula>, substitution: &Substitution) -> Rc<Formula> {
+        let substitute_all = |formulas: &Vec<Rc<Formula>>| {
+            formulas.iter().map(|formula| Formula::substitute(formula, substitution)).collect::<Vec<_>>()
+        };
+
+        match formula.borrow() {
+            Formula::RelationApplication(symbol, arguments) => Rc::new(Formula::RelationApplication(
+                symbol.clone(),
+                arguments.iter().map(|argument| Term::substitute(argument, substitution)).collect(),
+            )),
+            Formula::Equality(left, right) =>
+                Formula::new_equality(&Term::substitute(left, substitution), &Term::substitute(right, substitution)),
+            Formula::Negation(formula) => Formula::new_negation(&Formula::substitute(formula, substitution)),
+            Formula::Implication(left, right) =>
+                Formula::new_implication(&Formula::substitute(left, substitution), &Formula::substitute(right, substitution)),
+            Formula::Equivalence(left, right) =>
+                Formula::new_equivalence(&Formula::substitute(left, substitution), &Formula::substitute(right, substitution)),
+            Formula::Conjunction(conjuncts) => Rc::new(Formula::Conjunction(substitute_all(conjuncts))),
+            Formula::Disjunction(disjuncts) => Rc::new(Formula::Disjunction(substitute_all(disjuncts))),
+            Formula::UniversalQuantification(variable, body) => {
+                let (variable, body) = Formula::substitute_binder(variable, body, substitution);
+                Rc::new(Formula::UniversalQuantification(variable, body))
+            },
+            Formula::ExistentialQuantification(variable, body) => {
+                let (variable, body) = Formula::substitute_binder(variable, body, substitution);
+                Rc::new(Formula::ExistentialQuantification(variable, body))
+            },
+        }
+    }
+
+    /// Substitute in the body of a quantifier binding the given variable,
+    /// returning the possibly renamed variable together with the new body
+    fn substitute_binder(variable: &Rc<Variable>, body: &Rc<Formula>, substitution: &Substitution) -> (Rc<Variable>, Rc<Formula>) {
+        // only the free variables of the body other than the bound one are replaced
+        let mut inner = Substitution::new();
+        let mut replacement_vars = VariableSet::new();
+        for free_var in body.get_free_variables() {
+            if let Some(replacement) = substitution.get(&free_var) {
+                if &free_var != variable {
+                    replacement.collect_free_variables_in_set(&mut replacement_vars);
+                    inner.insert(free_var, replacement.clone());
+                }
+            }
+        }
+
+        if !replacement_vars.contains(variable) {
+            return (variable.clone(), Formula::substitute(body, &inner));
+        }
+
+        // the bound variable would capture a variable of a replacing term,
+        // so rename it to a variable that is neither free in the body nor in the replacements
+        let mut avoid = body.get_free_variables();
+        avoid.extend(replacement_vars);
+        let renamed = Variable::new_fresh(&variable.sort, &avoid);
+        inner.insert(variable.clone(), Rc::new(Term::Variable(renamed.clone())));
+
+        (renamed, Formula::substitute(body, &inner))
+    }
+
+    /// Get the largest index of a variable occurring in the formula, free or bound
+    fn get_max_variable_index(&self) -> Option<VariableIndex> {
+        let mut variables = self.get_free_variables();
+        self.collect_bound_variables(&mut variables);
+        variables.iter().map(|variable| variable.index).max()
+    }
+
+    fn collect_bound_variables(&self, variables: &mut VariableSet) {
+        match self {
+            Formula::RelationApplication(..) | Formula::Equality(..) => {},
+            Formula::Negation(formula) => formula.collect_bound_variables(variables),
+            Formula::Implication(left, right) | Formula::Equivalence(left, right) => {
+                left.collect_bound_variables(variables);
+                right.collect_bound_variables(variables);
+            },
+            Formula::Conjunction(formulas) | Formula::Disjunction(formulas) => {
+                for formula in formulas {
+                    formula.collect_bound_variables(variables);
+                }
+            },
+            Formula::UniversalQuantification(variable, body) | Formula::ExistentialQuantification(variable, body) => {
+                variables.insert(variable.clone());
+                body.collect_bound_variables(variables);
+            },
+        }
+    }
+
+    /// Rewrite the equivalences with quantified sides as conjunctions of implications,
+    /// and rename every bound variable to a distinct variable with index at least next_index
+    fn rename_apart(formula: &Rc<Formula>, next_index: &mut VariableIndex) -> Rc<Formula> {
+        let rename_all = |formulas: &Vec<Rc<Formula>>, next_index: &mut VariableIndex| {
+            formulas.iter().map(|formula| Formula::rename_apart(formula, next_index)).collect::<Vec<_>>()
+        };
+
+        match formula.borrow() {
+            Formula::RelationApplication(..) | Formula::Equality(..) => formula.clone(),
+            Formula::Negation(formula) => Formula::new_negation(&Formula::rename_apart(formula, next_index)),
+            Formula::Implication(left, right) => {
+                let left = Formula::rename_apart(left, next_index);
+                Formula::new_implication(&left, &Formula::rename_apart(right, next_index))
+            },
+            Formula::Equivalence(left, right) if left.has_quantifiers() || right.has_quantifiers() => {
+                // both copies of each side are renamed separately
+                let implications = Formula::new_conjunction(&[
+                    &Formula::new_implication(left, right),
+                    &Formula::new_implication(right, left),
+                ]);
+                Formula::rename_apart(&implications, next_index)
+            },
+            Formula::Equivalence(..) => formula.clone(),
+            Formula::Conjunction(conjuncts) => Rc::new(Formula::Conjunction(rename_all(conjuncts, next_index))),
+            Formula::Disjunction(disjuncts) => Rc::new(Formula::Disjunction(rename_all(disjuncts, next_index))),
+            Formula::UniversalQuantification(variable, body) | Formula::ExistentialQuantification(variable, body) => {
+                let renamed = Rc::new(Variable { index: *next_index, sort: variable.sort.clone() });
+                *next_index += 1;
+
+                let substitution = Substitution::from([(variable.clone(), Rc::new(Term::Variable(renamed.clone())))]);
+                let body = Formula::rename_apart(&Formula::substitute(body, &substitution), next_index);
+
+                if let Formula::UniversalQuantification(..) = formula.borrow() {
+                    Rc::new(Formula::UniversalQuantification(renamed, body))
+                } else {
+                    Rc::new(Formula::ExistentialQuantification(renamed, body))
+                }
+            },
+        }
+    }
+
+    /// Split a formula whose bound variables are distinct and not free anywhere into a
+    /// quantifier prefix, outermost first, with true for universal quantifiers, and a matrix
+    fn pull_quantifiers(formula: &Rc<Formula>) -> (Vec<(bool, Rc<Variable>)>, Rc<Formula>) {
+        let flip = |prefix: Vec<(bool, Rc<Variable>)>| {
+            prefix.into_iter().map(|(universal, variable)| (!universal, variable)).collect::<Vec<_>>()
+        };
+
+        match formula.borrow() {
+            Formula::Negation(formula) => {
+                let (prefix, matrix) = Formula::pull_quantifiers(formula);
+                (flip(prefix), Formula::new_negation(&matrix))
+            },
+            Formula::Implication(left, right) => {
+                let (left_prefix, left) = Formula::pull_quantifiers(left);
+                let (right_prefix, right) = Formula::pull_quantifiers(right);
+                ([flip(left_prefix), right_prefix].concat(), Formula::new_implication(&left, &right))
+            },
+            Formula::Conjunction(formulas) | Formula::Disjunction(formulas) => {
+                let mut prefix = vec![];
+                let mut matrices = vec![];
+                for formula in formulas {
+                    let (formula_prefix, matrix) = Formula::pull_quantifiers(formula);
+                    prefix.extend(formula_prefix);
+                    matrices.push(matrix);
+                }
+
+                if let Formula::Conjunction(..) = formula.borrow() {
+                    (prefix, Rc::new(Formula::Conjunction(matrices)))
+                } else {
+                    (prefix, Rc::new(Formula::Disjunction(matrices)))
+                }
+            },
+            Formula::UniversalQuantification(variable, body) | Formula::ExistentialQuantification(variable, body) => {
+                let (body_prefix, matrix) = Formula::pull_quantifiers(body);
+                let universal = matches!(formula.borrow(), Formula::UniversalQuantification(..));
+                ([vec![(universal, variable.clone())], body_prefix].concat(), matrix)
+            },
+            // atomic formulas, and equivalences, which have no quantifiers after renaming apart
+            _ => (vec![], formula.clone()),
+        }
+    }
+
+    /// Convert a formula to an equivalent formula in prenex normal form, i.e. a sequence
+    /// of quantifiers followed by a quantifier-free matrix. Bound variables are renamed
+    /// to fresh variables so that moving the quantifiers outwards does not capture
+    pub fn to_prenex(formula: &Rc<Formula>) -> Rc<Formula> {
+        let mut next_index = formula.get_max_variable_index().map_or(0, |index| index + 1);
+        let renamed = Formula::rename_apart(formula, &mut next_index);
+        let (prefix, matrix) = Formula::pull_quantifiers(&renamed);
+
+        prefix.into_iter().rev().fold(matrix, |body, (universal, variable)| {
+            if universal {
+                Rc::new(Formula::UniversalQuantification(variable, body))
+            } else {
+                Rc::new(Formula::ExistentialQuantification(variable, body))
+            }
+        })
+    }
+
+    /// Convert a formula to an equisatisfiable universal formula in prenex normal form,
+    /// replacing each existentially quantified variable by an application of a fresh
+    /// function symbol to the universally quantified variables before it.
+    /// Returns the formula and the language extended with the Skolem functions
+    pub fn skolemize(formula: &Rc<Formula>, language: &Rc<Language>) -> (Rc<Formula>, Rc<Language>) {
+        let mut universals = vec![];
+        let mut substitution = Substitution::new();
+        let mut skolem_functions: Vec<Rc<FunctionSymbol>> = vec![];
+        let mut next_skolem_index = 0;
+
+        let mut current = Formula::to_prenex(formula);
+        loop {
+            match current.clone().borrow() {
+                Formula::UniversalQuantification(variable, body) => {
+                    universals.push(variable.clone());
+                    current = body.clone();
+                },
+                Formula::ExistentialQuantification(variable, body) => {
+                    // pick a name that is not used by a function or relation symbol in the language
+                    let name = loop {
+                        let name = format!("sk{}", next_skolem_index);
+                        next_skolem_index += 1;
+                        if language.iter_function_symbols().all(|symbol| symbol.get_name() != name)
+                            && language.iter_relation_symbols().all(|symbol| symbol.get_name() != name) {
+                            break name;
+                        }
+                    };
+
+                    let input_sorts = universals.iter().map(|variable| &variable.sort).collect::<Vec<_>>();
+                    let symbol = FunctionSymbol::new(&name, &input_sorts, &variable.sort);
+                    let arguments = universals.iter().map(|variable| Rc::new(Term::Variable(variable.clone()))).collect::<Vec<_>>();
+
+                    substitution.insert(variable.clone(), Term::new_application(&symbol, &arguments.iter().collect::<Vec<_>>()));
+                    skolem_functions.push(symbol);
+                    current = body.clone();
+                },
+                _ => break,
+            }
+        }
+
+        // the variables are distinct after renaming apart, so the substitution does not capture
+        let matrix = Formula::substitute(&current, &substitution);
+        let skolemized = universals.into_iter().rev().fold(matrix, |body, variable| {
+            Rc::new(Formula::UniversalQuantification(variable, body))
+        });
+
+        let sorts = language.iter_sorts().collect::<Vec<_>>();
+        let functions = language.iter_function_symbols().chain(skolem_functions.iter()).collect::<Vec<_>>();
+        let relations = language.iter_relation_symbols().collect::<Vec<_>>();
+
+        (skolemized, Language::new(&sorts, &functions, &relations))
+    }
 }
 
 impl fmt::Display for Sort {
@@ -310,6 +855,43 @@ impl fmt::Display for FunctionSymbol {
     }
 }
 
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortError::FunctionArity { term, expected, found } =>
+                write!(f, "{}: expected {} arguments, found {}", term, expected, found),
+            SortError::RelationArity { formula, expected, found } =>
+                write!(f, "{}: expected {} arguments, found {}", formula, expected, found),
+            SortError::FunctionArgument { term, position, expected, found } =>
+                write!(f, "{}: argument {} should have sort {}, found {}", term, position + 1, expected, found),
+            SortError::RelationArgument { formula, position, expected, found } =>
+                write!(f, "{}: argument {} should have sort {}, found {}", formula, position + 1, expected, found),
+            SortError::Equality { formula, left, right } =>
+                write!(f, "{}: equality between sorts {} and {}", formula, left, right),
+            SortError::UnknownFunction { term } => match term.borrow() {
+                Term::Application(symbol, _) => write!(f, "{}: function symbol {} is not in the language", term, symbol),
+                Term::Variable(_) => unreachable!(),
+            },
+            SortError::UnknownRelation { formula } => match formula.borrow() {
+                Formula::RelationApplication(symbol, _) => write!(f, "{}: relation symbol {} is not in the language", formula, symbol),
+                _ => unreachable!(),
+            },
+            SortError::UnknownSort { variable } =>
+                write!(f, "{}: sort {} is not in the language", variable, variable.sort),
+        }
+    }
+}
+
+impl fmt::Display for SmtLibPrintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmtLibPrintError::Quantifier { formula } => write!(f, "{}: quantifiers are not supported in QF_UF", formula),
+            SmtLibPrintError::FreeVariable { formula, variable } => write!(f, "{}: free variable {} would read back as a constant", formula, variable),
+            SmtLibPrintError::Name { name } => write!(f, "{}: names with | or \\ cannot be written in SMT-LIB 2", name),
+        }
+    }
+}
+
 impl fmt::Display for Variable {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "x{}:{}", self.index, self.sort)
@@ -395,9 +977,264 @@ impl fmt::Display for Formula {
     }
 }
 
-// TODO
-// 1. format
-// 2. free variables
-// 3. (potentially not capture free) substitution
-// 4. sort check
-// 5. Skolemization
+/// Formats a sort, variable, term or formula in the syntax of SMT-LIB 2.
+/// Variable x{index} of sort S is written as the symbol `{prefix}{index}_S`,
+/// where the prefix is "x" unless given by `with_variable_prefix`
+pub struct SmtLib<'a, T: ?Sized> {
+    value: &'a T,
+    variable_prefix: &'a str,
+}
+
+impl<'a, T: ?Sized> SmtLib<'a, T> {
+    pub fn new(value: &'a T) -> SmtLib<'a, T> {
+        SmtLib { value, variable_prefix: "x" }
+    }
+
+    /// Use a prefix for variable names, such as `Language::get_smtlib_variable_prefix`,
+    /// so that they do not clash with the symbols of a language
+    pub fn with_variable_prefix(value: &'a T, variable_prefix: &'a str) -> SmtLib<'a, T> {
+        SmtLib { value, variable_prefix }
+    }
+
+    /// Format a part of the value with the same variable prefix
+    fn part<U: ?Sized>(&self, value: &'a U) -> SmtLib<'a, U> {
+        SmtLib { value, variable_prefix: self.variable_prefix }
+    }
+}
+
+/// Reserved words of SMT-LIB 2, which cannot be used as simple symbols
+const SMTLIB_RESERVED_WORDS: &[&str] = &[
+    "!", "_", "as", "BINARY", "DECIMAL", "exists", "forall", "HEXADECIMAL", "let", "match", "NUMERAL", "par", "STRING",
+    "assert", "check-sat", "check-sat-assuming", "declare-const", "declare-datatype", "declare-datatypes",
+    "declare-fun", "declare-sort", "define-fun", "define-fun-rec", "define-funs-rec", "define-sort", "echo", "exit",
+    "get-assertions", "get-assignment", "get-info", "get-model", "get-option", "get-proof", "get-unsat-assumptions",
+    "get-unsat-core", "get-value", "pop", "push", "reset", "reset-assertions", "set-info", "set-logic", "set-option",
+];
+
+/// Quote a name with bars unless it is a simple symbol of SMT-LIB 2.
+/// Note that names containing `|` or `\` cannot be written in SMT-LIB 2
+fn smtlib_symbol(name: &str) -> String {
+    let is_simple = name.chars().next().is_some_and(|first| !first.is_ascii_digit()) &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || "~!@$%^&*_-+=<>.?/".contains(c)) &&
+        !SMTLIB_RESERVED_WORDS.contains(&name);
+
+    if is_simple {
+        name.to_string()
+    } else {
+        format!("|{}|", name)
+    }
+}
+
+/// Write an application in SMT-LIB 2 syntax, omitting the parentheses if there are no arguments
+fn write_smtlib_application<T: ?Sized>(f: &mut fmt::Formatter<'_>, variable_prefix: &str, name: &str, arguments: &[Rc<T>]) -> fmt::Result
+where for<'a> SmtLib<'a, T>: fmt::Display {
+    if arguments.is_empty() {
+        return write!(f, "{}", name);
+    }
+    write!(f, "({}", name)?;
+    for argument in arguments {
+        write!(f, " {}", SmtLib::with_variable_prefix(argument.as_ref(), variable_prefix))?;
+    }
+    write!(f, ")")
+}
+
+impl fmt::Display for SmtLib<'_, Sort> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", smtlib_symbol(&self.value.name))
+    }
+}
+
+impl fmt::Display for SmtLib<'_, Variable> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", smtlib_symbol(&format!("{}{}_{}", self.variable_prefix, self.value.index, self.value.sort.name)))
+    }
+}
+
+impl fmt::Display for SmtLib<'_, Term> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Term::Variable(variable) => write!(f, "{}", self.part(variable.as_ref())),
+            Term::Application(symbol, arguments) =>
+                write_smtlib_application(f, self.variable_prefix, &smtlib_symbol(&symbol.name), arguments),
+        }
+    }
+}
+
+impl fmt::Display for SmtLib<'_, Formula> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Formula::RelationApplication(symbol, arguments) =>
+                write_smtlib_application(f, self.variable_prefix, &smtlib_symbol(&symbol.name), arguments),
+            Formula::Equality(left, right) =>
+                write!(f, "(= {} {})", self.part(left.as_ref()), self.part(right.as_ref())),
+            Formula::Negation(formula) => write!(f, "(not {})", self.part(formula.as_ref())),
+            Formula::Implication(left, right) =>
+                write!(f, "(=> {} {})", self.part(left.as_ref()), self.part(right.as_ref())),
+            Formula::Equivalence(left, right) =>
+                write!(f, "(= {} {})", self.part(left.as_ref()), self.part(right.as_ref())),
+            Formula::Conjunction(conjuncts) if conjuncts.is_empty() => write!(f, "true"),
+            Formula::Conjunction(conjuncts) => write_smtlib_application(f, self.variable_prefix, "and", conjuncts),
+            Formula::Disjunction(disjuncts) if disjuncts.is_empty() => write!(f, "false"),
+            Formula::Disjunction(disjuncts) => write_smtlib_application(f, self.variable_prefix, "or", disjuncts),
+            Formula::UniversalQuantification(variable, body) =>
+                write!(f, "(forall (({} {})) {})", self.part(variable.as_ref()), self.part(variable.sort.as_ref()), self.part(body.as_ref())),
+            Formula::ExistentialQuantification(variable, body) =>
+                write!(f, "(exists (({} {})) {})", self.part(variable.as_ref()), self.part(variable.sort.as_ref()), self.part(body.as_ref())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitution_avoids_capture() {
+        let sort_a = Sort::new("A");
+        let function_f = FunctionSymbol::new("f", &[&sort_a, &sort_a], &sort_a);
+        let x0 = Rc::new(Variable { index: 0, sort: sort_a.clone() });
+        let x1 = Rc::new(Variable { index: 1, sort: sort_a.clone() });
+        let t0 = Rc::new(Term::Variable(x0.clone()));
+        let t1 = Rc::new(Term::Variable(x1.clone()));
+
+        // ∀x0 (f(x0, x1) = x1) [x1 := f(x0, x0)] renames the bound x0
+        let formula = Rc::new(Formula::UniversalQuantification(
+            x0.clone(),
+            Formula::new_equality(&Term::new_application(&function_f, &[&t0, &t1]), &t1),
+        ));
+        let substitution = Substitution::from([(x1.clone(), Term::new_application(&function_f, &[&t0, &t0]))]);
+        let result = Formula::substitute(&formula, &substitution);
+        assert_eq!(result.to_string(), "∀x2:A (f(x2:A, f(x0:A, x0:A)) = f(x0:A, x0:A))");
+        assert_eq!(result.get_free_variables(), VariableSet::from([x0.clone()]));
+
+        // bound occurrences are not replaced, and no renaming is needed
+        let substitution = Substitution::from([(x0.clone(), t1.clone())]);
+        assert_eq!(Formula::substitute(&formula, &substitution).to_string(), formula.to_string());
+
+        // a free occurrence outside of the binder is replaced
+        let formula = Formula::new_conjunction(&[&Formula::new_equality(&t0, &t1), &formula]);
+        let substitution = Substitution::from([(x0.clone(), t1.clone()), (x1.clone(), t0.clone())]);
+        assert_eq!(
+            Formula::substitute(&formula, &substitution).to_string(),
+            "(x1:A = x0:A ∧ ∀x2:A (f(x2:A, x0:A) = x0:A))",
+        );
+    }
+
+    #[test]
+    fn prenex_and_skolemization() {
+        let sort_a = Sort::new("A");
+        let function_f = FunctionSymbol::new("f", &[&sort_a], &sort_a);
+        let constant_sk0 = FunctionSymbol::new("sk0", &[], &sort_a);
+        let relation_p = RelationSymbol::new("P", &[&sort_a]);
+        let relation_r = RelationSymbol::new("R", &[&sort_a, &sort_a]);
+        let language = Language::new(&[&sort_a], &[&function_f, &constant_sk0], &[&relation_p, &relation_r]);
+
+        let x0 = Rc::new(Variable { index: 0, sort: sort_a.clone() });
+        let x1 = Rc::new(Variable { index: 1, sort: sort_a.clone() });
+        let t0 = Rc::new(Term::Variable(x0.clone()));
+        let t1 = Rc::new(Term::Variable(x1.clone()));
+
+        // (∀x0 P(x0)) → ∀x0 ∃x1 (R(x0, x1) ∧ ¬∃x0 R(x1, x0))
+        let formula = Formula::new_implication(
+            &Rc::new(Formula::UniversalQuantification(x0.clone(), Formula::new_relation_application(&relation_p, &[&t0]))),
+            &Rc::new(Formula::UniversalQuantification(x0.clone(), Rc::new(Formula::ExistentialQuantification(
+                x1.clone(),
+                Formula::new_conjunction(&[
+                    &Formula::new_relation_application(&relation_r, &[&t0, &t1]),
+                    &Formula::new_negation(&Rc::new(Formula::ExistentialQuantification(
+                        x0.clone(),
+                        Formula::new_relation_application(&relation_r, &[&t1, &t0]),
+                    ))),
+                ]),
+            )))),
+        );
+
+        let prenex = Formula::to_prenex(&formula);
+        assert_eq!(
+            prenex.to_string(),
+            "∃x2:A (∀x3:A (∃x4:A (∀x5:A ((P(x2:A) → (R(x3:A, x4:A) ∧ ¬(R(x4:A, x5:A))))))))",
+        );
+
+        // sk0 is already in the language
+        let (skolemized, skolem_language) = Formula::skolemize(&formula, &language);
+        assert_eq!(
+            skolemized.to_string(),
+            "∀x3:A (∀x5:A ((P(sk1()) → (R(x3:A, sk2(x3:A)) ∧ ¬(R(sk2(x3:A), x5:A))))))",
+        );
+        assert_eq!(skolem_language.iter_function_symbols().count(), 4);
+        assert!(skolem_language.check_formula(&skolemized).is_empty());
+
+        // quantified sides of an equivalence are renamed separately in each direction
+        let formula = Formula::new_equivalence(
+            &Rc::new(Formula::ExistentialQuantification(x0.clone(), Formula::new_relation_application(&relation_p, &[&t0]))),
+            &Formula::new_relation_application(&relation_p, &[&Term::new_application(&function_f, &[&t1])]),
+        );
+        assert_eq!(
+            Formula::to_prenex(&formula).to_string(),
+            "∀x2:A (∃x3:A (((P(x2:A) → P(f(x1:A))) ∧ (P(f(x1:A)) → P(x3:A)))))",
+        );
+    }
+
+    #[test]
+    fn skolem_names_avoid_relations() {
+        // ∃x0 (P(x0) ∧ sk0), where sk0 is a nullary relation symbol
+        let sort_a = Sort::new("A");
+        let relation_p = RelationSymbol::new("P", &[&sort_a]);
+        let relation_sk0 = RelationSymbol::new("sk0", &[]);
+        let language = Language::new(&[&sort_a], &[], &[&relation_p, &relation_sk0]);
+
+        let x0 = Rc::new(Variable { index: 0, sort: sort_a.clone() });
+        let formula = Rc::new(Formula::ExistentialQuantification(x0.clone(), Formula::new_conjunction(&[
+            &Formula::new_relation_application(&relation_p, &[&Rc::new(Term::Variable(x0))]),
+            &Formula::new_relation_application(&relation_sk0, &[]),
+        ])));
+
+        let (skolemized, skolem_language) = Formula::skolemize(&formula, &language);
+        assert_eq!(skolemized.to_string(), "(P(sk1()) ∧ sk0())");
+        assert!(skolem_language.iter_function_symbols().all(|symbol| symbol.get_name() == "sk1"));
+        assert!(skolem_language.check_formula(&skolemized).is_empty());
+    }
+
+    #[test]
+    fn sort_errors() {
+        let sort_a = Sort::new("A");
+        let sort_b = Sort::new("B");
+        let constant_a = FunctionSymbol::new("a", &[], &sort_a);
+        let constant_b = FunctionSymbol::new("b", &[], &sort_b);
+        let constant_c = FunctionSymbol::new("c", &[], &sort_a);
+        let function_f = FunctionSymbol::new("f", &[&sort_a], &sort_b);
+        let relation_p = RelationSymbol::new("P", &[&sort_b]);
+        let language = Language::new(&[&sort_a, &sort_b], &[&constant_a, &constant_b, &function_f], &[&relation_p]);
+
+        let a = Term::new_application(&constant_a, &[]);
+        let b = Term::new_application(&constant_b, &[]);
+        let c = Term::new_application(&constant_c, &[]);
+        let fa = Term::new_application(&function_f, &[&a]);
+        let fb = Term::new_application(&function_f, &[&b]);
+
+        let well_sorted = Formula::new_conjunction(&[
+            &Formula::new_equality(&fa, &b),
+            &Formula::new_relation_application(&relation_p, &[&fa]),
+        ]);
+        assert!(language.check_formula(&well_sorted).is_empty());
+
+        let errors = language.check_term(&fb);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "f(b()): argument 1 should have sort A, found B");
+
+        let ill_sorted = Formula::new_conjunction(&[
+            &Formula::new_equality(&a, &b),
+            &Formula::new_relation_application(&relation_p, &[&a]),
+            &Formula::new_equality(&c, &a),
+            &Formula::new_equality(&Term::new_variable(0, &Sort::new("C")), &Term::new_variable(1, &Sort::new("C"))),
+        ]);
+        let errors = language.check_formula(&ill_sorted).iter().map(|error| error.to_string()).collect::<Vec<_>>();
+        assert_eq!(errors, vec![
+            "a() = b(): equality between sorts A and B",
+            "P(a()): argument 1 should have sort B, found A",
+            "c(): function symbol c: -> A is not in the language",
+            "x0:C: sort C is not in the language",
+            "x1:C: sort C is not in the language",
+        ]);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod ackermann;
+pub mod congruence;
+pub mod fol;
+pub mod model;
+pub mod sat;
+pub mod smtlib;
+pub mod solver;
+pub mod tptp;
+pub mod parser;
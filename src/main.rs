@@ -1,41 +1,123 @@
-mod congruence;
-mod fol;
-mod solver;
-mod parser;
-
 use std::io::Write;
 
-use fol::*;
-use solver::*;
+use euf::fol::*;
+use euf::parser;
+use euf::smtlib::*;
+use euf::solver::*;
+use euf::tptp::*;
+
+fn read_file(path: &str) -> String {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            std::process::exit(1);
+        },
+    }
+}
+
+/// Run an SMT-LIB 2 script and print the response of each command
+fn run_smtlib_script(path: &str) {
+    let script = read_file(path);
+    let mut executor = ScriptExecutor::new();
+    for command in ScriptReader::new(&script) {
+        match command {
+            Ok(command) => if let Some(response) = executor.execute(&command) {
+                println!("{}", response);
+            },
+            Err(error) => {
+                eprintln!("{}:{}\n{}", path, error, error.diagnostic(&script));
+                std::process::exit(1);
+            },
+        }
+    }
+}
+
+/// Solve a TPTP problem and print its SZS status
+fn run_tptp_problem(path: &str, instantiation_depth: Option<usize>) {
+    let input = read_file(path);
+    let problem = match TptpProblem::parse(&input) {
+        Ok(problem) => problem,
+        Err(error) => {
+            eprintln!("{}:{}\n{}", path, error, error.diagnostic(&input));
+            std::process::exit(1);
+        },
+    };
+
+    let status = problem.solve(instantiation_depth);
+    println!("% SZS status {} for {}", status, path);
+    if status == SzsStatus::Inappropriate {
+        println!("% the problem has quantifiers, which are outside the decidable fragment; use --instantiate <depth> to try ground instances");
+    }
+}
 
 fn main() {
+    // usage: euf [--instantiate <depth>] [<file>.smt2 | <file>.p]
+    let mut arguments = std::env::args().skip(1);
+    let mut instantiation_depth = None;
+    let mut path = None;
+    while let Some(argument) = arguments.next() {
+        if argument == "--instantiate" {
+            match arguments.next().and_then(|depth| depth.parse().ok()) {
+                Some(depth) => instantiation_depth = Some(depth),
+                None => {
+                    eprintln!("--instantiate expects a depth");
+                    std::process::exit(1);
+                },
+            }
+        } else {
+            path = Some(argument);
+        }
+    }
+
+    if let Some(path) = path {
+        if path.ends_with(".p") || path.ends_with(".ax") || path.ends_with(".tptp") {
+            run_tptp_problem(&path, instantiation_depth);
+        } else {
+            run_smtlib_script(&path);
+        }
+        return;
+    }
+
     // let formula = r"f(f(f(a))) = a /\ f(f(f(f(f(a))))) = a /\ f(a) != a";
     // println!("{}: {}", parse_and_check_sat(formula), formula);
 
     let mut input = String::new();
 
+    // symbols are inferred in sort A for each formula until the first
+    // declaration, which persists across lines like all later ones
+    let sort_a = Sort::new("A");
+    let mut parser = parser::UnsortedParser::new(&sort_a);
+
     loop {
         print!(">>> ");
         input.clear();
         std::io::stdout().flush().unwrap();
         std::io::stdin().read_line(&mut input).unwrap();
 
-        let sort_a = Sort::new("A");
-        let mut parser = parser::UnsortedParser::new(&sort_a);
+        parser.forget_inferred_symbols();
 
         let input_trimmed = input.trim();
 
-        match parser.parse_formula(input_trimmed) {
-            Some((rest, formula)) => {
-                if rest.trim().is_empty() {
-                    println!("parsed: {}", formula);
-                    println!("{}", QFEUFSolver::sat(&parser.get_language(), &formula));
-                } else {
-                    println!("failed to parse: {}", input_trimmed);
+        match parser.parse_statement(input_trimmed) {
+            Ok(parser::Statement::Declarations(declarations)) => {
+                for declaration in declarations {
+                    match declaration {
+                        parser::Declaration::Sort(sort) => println!("declared: sort {}", sort),
+                        parser::Declaration::Function(symbol) => println!("declared: {}", symbol),
+                    }
+                }
+            },
+            Ok(parser::Statement::Formula(formula)) => {
+                println!("parsed: {}", formula);
+                let result = QFEUFSolver::sat(&parser.get_language(), &formula);
+                println!("{}", result);
+                if let SatResult::Sat(model) = result {
+                    println!("{}", model);
                 }
             },
-            None => {
-                println!("failed to parse: {}", input_trimmed);
+            Err(error) => {
+                println!("failed to parse:\n{}", error.diagnostic(input_trimmed));
             },
         }
     }
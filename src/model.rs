@@ -0,0 +1,329 @@
+//! Finite structures for many-sorted first-order logic
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::fmt;
+
+use crate::fol::*;
+
+pub type Element = usize;
+
+/// Interpretation of a function symbol as a finite table,
+/// arguments not in the table are mapped to the default element
+struct FunctionInterpretation {
+    table: HashMap<Vec<Element>, Element>,
+    default: Option<Element>, // only None before the model is completed
+}
+
+/// A structure with a finite domain for a many-sorted language
+pub struct Model {
+    language: Rc<Language>,
+    domain: Vec<Rc<Sort>>, // sort of each element
+    functions: HashMap<Rc<FunctionSymbol>, FunctionInterpretation>,
+    relations: HashMap<Rc<RelationSymbol>, HashSet<Vec<Element>>>, // tuples at which each relation holds
+    variables: Assignment, // values of the free variables of the formula the model is built for
+}
+
+pub type Assignment = HashMap<Rc<Variable>, Element>;
+
+impl Model {
+    /// Create a model with an empty domain
+    pub fn new(language: &Rc<Language>) -> Model {
+        Model {
+            language: language.clone(),
+            domain: vec![],
+            functions: HashMap::new(),
+            relations: HashMap::new(),
+            variables: Assignment::new(),
+        }
+    }
+
+    /// Add a new element of the given sort to the domain
+    pub fn add_element(&mut self, sort: &Rc<Sort>) -> Element {
+        self.domain.push(sort.clone());
+        self.domain.len() - 1
+    }
+
+    /// Get the number of elements in the domain
+    pub fn get_num_elements(&self) -> usize {
+        self.domain.len()
+    }
+
+    pub fn get_sort(&self, element: Element) -> &Rc<Sort> {
+        &self.domain[element]
+    }
+
+    /// Iterate through the elements of the given sort
+    pub fn iter_elements<'a>(&'a self, sort: &'a Rc<Sort>) -> impl Iterator<Item = Element> + 'a {
+        (0..self.domain.len()).filter(move |element| &self.domain[*element] == sort)
+    }
+
+    /// Set the value of a function symbol at the given arguments
+    pub fn set_function_value(&mut self, symbol: &Rc<FunctionSymbol>, arguments: Vec<Element>, value: Element) {
+        self.functions.entry(symbol.clone())
+            .or_insert_with(|| FunctionInterpretation { table: HashMap::new(), default: None })
+            .table.insert(arguments, value);
+    }
+
+    /// Get the value of a function symbol at the given arguments
+    pub fn get_function_value(&self, symbol: &Rc<FunctionSymbol>, arguments: &[Element]) -> Option<Element> {
+        let interpretation = self.functions.get(symbol)?;
+        interpretation.table.get(arguments).copied().or(interpretation.default)
+    }
+
+    /// Set the value of a free variable
+    pub fn set_variable_value(&mut self, variable: &Rc<Variable>, value: Element) {
+        self.variables.insert(variable.clone(), value);
+    }
+
+    /// Get the value of a free variable
+    pub fn get_variable_value(&self, variable: &Rc<Variable>) -> Option<Element> {
+        self.variables.get(variable).copied()
+    }
+
+    /// Get the values of all free variables
+    pub fn get_assignment(&self) -> &Assignment {
+        &self.variables
+    }
+
+    /// Set whether a relation symbol holds at the given arguments
+    pub fn set_relation_value(&mut self, symbol: &Rc<RelationSymbol>, arguments: Vec<Element>, value: bool) {
+        let interpretation = self.relations.entry(symbol.clone()).or_default();
+        if value {
+            interpretation.insert(arguments);
+        } else {
+            interpretation.remove(&arguments);
+        }
+    }
+
+    /// Get whether a relation symbol holds at the given arguments
+    pub fn get_relation_value(&self, symbol: &Rc<RelationSymbol>, arguments: &[Element]) -> Option<bool> {
+        Some(self.relations.get(symbol)?.contains(arguments))
+    }
+
+    /// Make sure that every sort has at least one element and every
+    /// function and relation symbol in the language is interpreted, filling all
+    /// unconstrained entries with the first element of the output sort or false
+    pub fn complete(&mut self) {
+        let language = self.language.clone();
+
+        for sort in language.iter_sorts() {
+            if self.iter_elements(sort).next().is_none() {
+                self.add_element(sort);
+            }
+        }
+
+        for symbol in language.iter_function_symbols() {
+            self.functions.entry(symbol.clone())
+                .or_insert_with(|| FunctionInterpretation { table: HashMap::new(), default: None });
+        }
+
+        for symbol in language.iter_relation_symbols() {
+            self.relations.entry(symbol.clone()).or_default();
+        }
+
+        let symbols = self.functions.keys().cloned().collect::<Vec<_>>();
+        for symbol in symbols {
+            let output_sort = symbol.get_output_sort();
+            let first_element = self.iter_elements(output_sort).next();
+            let default = match first_element {
+                Some(element) => element,
+                None => self.add_element(output_sort),
+            };
+            self.functions.get_mut(&symbol).unwrap().default.get_or_insert(default);
+        }
+    }
+
+    /// Evaluate a term under an assignment of its free variables.
+    /// Variables not in the assignment take their values in the model
+    pub fn eval_term(&self, term: &Rc<Term>, assignment: &Assignment) -> Option<Element> {
+        match term.borrow() {
+            Term::Variable(variable) => assignment.get(variable).or(self.variables.get(variable)).copied(),
+            Term::Application(symbol, arguments) => {
+                let arguments = arguments.iter()
+                    .map(|argument| self.eval_term(argument, assignment))
+                    .collect::<Option<Vec<_>>>()?;
+                self.get_function_value(symbol, &arguments)
+            }
+        }
+    }
+
+    /// Evaluate a formula under an assignment of its free variables.
+    /// Returns None if some symbol or variable is not interpreted
+    pub fn eval_formula(&self, formula: &Rc<Formula>, assignment: &Assignment) -> Option<bool> {
+        match formula.borrow() {
+            Formula::RelationApplication(symbol, arguments) => {
+                let arguments = arguments.iter()
+                    .map(|argument| self.eval_term(argument, assignment))
+                    .collect::<Option<Vec<_>>>()?;
+                self.get_relation_value(symbol, &arguments)
+            },
+            Formula::Equality(left, right) =>
+                Some(self.eval_term(left, assignment)? == self.eval_term(right, assignment)?),
+            Formula::Negation(formula) => Some(!self.eval_formula(formula, assignment)?),
+            Formula::Implication(left, right) =>
+                Some(!self.eval_formula(left, assignment)? || self.eval_formula(right, assignment)?),
+            Formula::Equivalence(left, right) =>
+                Some(self.eval_formula(left, assignment)? == self.eval_formula(right, assignment)?),
+            Formula::Conjunction(conjuncts) => {
+                let mut value = true;
+                for conjunct in conjuncts {
+                    value &= self.eval_formula(conjunct, assignment)?;
+                }
+                Some(value)
+            },
+            Formula::Disjunction(disjuncts) => {
+                let mut value = false;
+                for disjunct in disjuncts {
+                    value |= self.eval_formula(disjunct, assignment)?;
+                }
+                Some(value)
+            },
+            Formula::UniversalQuantification(variable, body) => {
+                let mut value = true;
+                for element in self.iter_elements(&variable.sort) {
+                    let mut assignment = assignment.clone();
+                    assignment.insert(variable.clone(), element);
+                    value &= self.eval_formula(body, &assignment)?;
+                }
+                Some(value)
+            },
+            Formula::ExistentialQuantification(variable, body) => {
+                let mut value = false;
+                for element in self.iter_elements(&variable.sort) {
+                    let mut assignment = assignment.clone();
+                    assignment.insert(variable.clone(), element);
+                    value |= self.eval_formula(body, &assignment)?;
+                }
+                Some(value)
+            },
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sorts = self.domain.clone();
+        sorts.sort_by(|sort1, sort2| sort1.get_name().cmp(sort2.get_name()));
+        sorts.dedup();
+
+        let mut lines = vec![];
+
+        for sort in &sorts {
+            let elements = self.iter_elements(sort).map(|element| format!("e{}", element)).collect::<Vec<_>>();
+            lines.push(format!("{} = {{{}}}", sort, elements.join(", ")));
+        }
+
+        let mut variables = self.variables.iter().collect::<Vec<_>>();
+        variables.sort_by_key(|(variable, _)| (variable.sort.get_name(), variable.index));
+
+        for (variable, value) in variables {
+            lines.push(format!("{} = e{}", variable, value));
+        }
+
+        let mut symbols = self.functions.keys().collect::<Vec<_>>();
+        symbols.sort_by(|symbol1, symbol2| symbol1.get_name().cmp(symbol2.get_name()));
+
+        for symbol in symbols {
+            let interpretation = &self.functions[symbol];
+
+            let mut entries = interpretation.table.iter().collect::<Vec<_>>();
+            entries.sort();
+
+            for (arguments, value) in entries {
+                if arguments.is_empty() {
+                    lines.push(format!("{} = e{}", symbol.get_name(), value));
+                } else {
+                    let arguments = arguments.iter().map(|argument| format!("e{}", argument)).collect::<Vec<_>>();
+                    lines.push(format!("{}({}) = e{}", symbol.get_name(), arguments.join(", "), value));
+                }
+            }
+
+            // the default value covers all arguments not in the table
+            match interpretation.default {
+                Some(default) if symbol.arity() != 0 => {
+                    let arguments = vec!["_"; symbol.arity()];
+                    lines.push(format!("{}({}) = e{}", symbol.get_name(), arguments.join(", "), default));
+                },
+                Some(default) if interpretation.table.is_empty() =>
+                    lines.push(format!("{} = e{}", symbol.get_name(), default)),
+                _ => {},
+            }
+        }
+
+        let mut symbols = self.relations.keys().collect::<Vec<_>>();
+        symbols.sort_by(|symbol1, symbol2| symbol1.get_name().cmp(symbol2.get_name()));
+
+        for symbol in symbols {
+            let mut entries = self.relations[symbol].iter().collect::<Vec<_>>();
+            entries.sort();
+
+            for arguments in entries {
+                if arguments.is_empty() {
+                    lines.push(format!("{} = true", symbol.get_name()));
+                } else {
+                    let arguments = arguments.iter().map(|argument| format!("e{}", argument)).collect::<Vec<_>>();
+                    lines.push(format!("{}({}) = true", symbol.get_name(), arguments.join(", ")));
+                }
+            }
+
+            // the relation is false at all other arguments
+            if symbol.arity() != 0 {
+                let arguments = vec!["_"; symbol.arity()];
+                lines.push(format!("{}({}) = false", symbol.get_name(), arguments.join(", ")));
+            } else if self.relations[symbol].is_empty() {
+                lines.push(format!("{} = false", symbol.get_name()));
+            }
+        }
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::UnsortedParser;
+    use crate::solver::{QFEUFSolver, SatResult};
+
+    #[test]
+    fn models_satisfy_input() {
+        for input in [
+            r"f(a) = f(f(f(a))) /\ f(a) != f(f(a))",
+            r"f(a, b) = c /\ g(c) != d /\ (a = b \/ g(d) = c)",
+            r"a != b /\ b != c /\ (a = c -> f(a) != f(c))",
+        ] {
+            let sort_a = Sort::new("A");
+            let mut parser = UnsortedParser::new(&sort_a);
+            let formula = parser.parse_formula(input).unwrap();
+
+            match QFEUFSolver::sat(&parser.get_language(), &formula) {
+                SatResult::Sat(model) => assert_eq!(model.eval_formula(&formula, &Assignment::new()), Some(true), "{}", input),
+                result => panic!("{}: expected sat, got {}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn complete_interprets_all_symbols() {
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+        let formula = parser.parse_formula(r"f(a) = b /\ g(b) = g(c)").unwrap();
+        let language = parser.get_language();
+
+        // only a and f(a) are interpreted before completion
+        let mut model = Model::new(&language);
+        let element = model.add_element(&sort_a);
+        let a = language.iter_function_symbols().find(|symbol| symbol.get_name() == "a").unwrap();
+        let f = language.iter_function_symbols().find(|symbol| symbol.get_name() == "f").unwrap();
+        model.set_function_value(a, vec![], element);
+        model.set_function_value(f, vec![element], element);
+        assert_eq!(model.eval_formula(&formula, &Assignment::new()), None);
+
+        model.complete();
+        assert_eq!(model.iter_elements(&sort_a).count(), 1);
+        assert_eq!(model.eval_formula(&formula, &Assignment::new()), Some(true));
+    }
+}
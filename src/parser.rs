@@ -1,5 +1,6 @@
 use std::rc::Rc;
 use std::iter;
+use std::fmt;
 use std::collections::HashMap;
 
 use crate::fol::*;
@@ -11,19 +12,20 @@ use nom::bytes::complete::tag;
 
 macro_rules! ws {
     ($x: expr) => {
-        delimited::<_, _, _, _, error::Error<&str>, _, _, _>(multispace0, $x, multispace0)
+        delimited::<_, _, _, _, SyntaxError, _, _, _>(multispace0, $x, multispace0)
     }
 }
 
 macro_rules! sep_list {
-    ($self: expr, $x: ident, $y: expr, $input: expr, $at_least_one: expr) => {
+    ($self: expr, $x: ident, $separator: expr, $input: expr, $at_least_one: expr) => {
         match $self.$x($input) {
-            IResult::Err(err) => if $at_least_one { IResult::Err(err) } else { IResult::Ok(($input, vec![])) },
+            IResult::Err(Err::Error(_)) if !$at_least_one => IResult::Ok(($input, vec![])),
+            IResult::Err(err) => IResult::Err(err),
             IResult::Ok((input, x)) => {
                 let mut input = input;
                 let mut xs = vec![x];
                 loop {
-                    match $y(input) {
+                    match $self.token($separator, input) {
                         IResult::Err(_) => return IResult::Ok((input, xs)),
                         IResult::Ok((rest, _)) => {
                             let (rest, x) = $self.$x(rest)?;
@@ -37,9 +39,175 @@ macro_rules! sep_list {
     };
 }
 
+/// Unicode symbols accepted in place of the ASCII tokens,
+/// so that formulas printed by `Display` can be parsed back
+const UNICODE_TOKENS: [(&str, &str); 5] = [
+    ("!", "¬"),
+    ("/\\", "∧"),
+    ("\\/", "∨"),
+    ("->", "→"),
+    ("<->", "⇔"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// None of the expected tokens occurs at the position
+    Unexpected { expected: Vec<&'static str> },
+    /// A function symbol is applied to a different number of arguments than before
+    ArityMismatch { symbol: String, expected: usize, found: usize },
+    /// A declaration refers to a sort that is not declared
+    UnknownSort { sort: String },
+    /// A function symbol is used without a declaration, after some sort or symbol is declared
+    UndeclaredSymbol { symbol: String },
+    /// A sort or a function symbol is declared twice
+    Redeclaration { name: String },
+    /// An argument of an application does not have the input sort of the function symbol
+    ArgumentSortMismatch { symbol: String, argument: usize, expected: String, found: String },
+    /// The two sides of an equality have different sorts
+    EqualitySortMismatch { left: String, right: String },
+    /// A bound variable is annotated with a sort different from its binder
+    SortMismatch { variable: String, expected: String, found: String },
+}
+
+/// A declaration of a sort or a function symbol, which is added to the language of the parser
+#[derive(Clone, Debug)]
+pub enum Declaration {
+    Sort(Rc<Sort>),
+    Function(Rc<FunctionSymbol>),
+}
+
+/// An input of the REPL, either a list of declarations or a formula
+#[derive(Clone, Debug)]
+pub enum Statement {
+    Declarations(Vec<Declaration>),
+    Formula(Rc<Formula>),
+}
+
+/// A parse error at a byte offset of the input,
+/// with the corresponding line and column (both starting from 1)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+/// Error type used internally by the nom parsers, where the position
+/// is still the remaining input. Failures that cannot be recovered
+/// from by backtracking carry their kind
+#[derive(Debug)]
+struct SyntaxError<'a> {
+    input: &'a str,
+    failure: Option<ParseErrorKind>,
+}
+
+impl<'a> error::ParseError<&'a str> for SyntaxError<'a> {
+    fn from_error_kind(input: &'a str, _: error::ErrorKind) -> Self {
+        SyntaxError { input, failure: None }
+    }
+
+    fn append(_: &'a str, _: error::ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+type ParseResult<'a, T> = IResult<&'a str, T, SyntaxError<'a>>;
+
+fn failure<T>(input: &str, kind: ParseErrorKind) -> ParseResult<'_, T> {
+    IResult::Err(Err::Failure(SyntaxError { input, failure: Some(kind) }))
+}
+
+/// Tries the second alternative only if the first one fails without committing
+fn or_else<'a, T>(first: ParseResult<'a, T>, second: impl FnOnce() -> ParseResult<'a, T>) -> ParseResult<'a, T> {
+    match first {
+        IResult::Err(Err::Error(_)) => second(),
+        result => result,
+    }
+}
+
+/// Get the line and column (both starting from 1) of a byte offset in the source
+pub(crate) fn get_line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+/// Formats a message as the given line of the source
+/// followed by a caret under the column and the message
+pub(crate) fn format_diagnostic(source: &str, line: usize, column: usize, message: impl fmt::Display) -> String {
+    let source_line = source.lines().nth(line - 1).unwrap_or("");
+    format!("{}\n{}^ {}", source_line, " ".repeat(column - 1), message)
+}
+
+impl ParseError {
+    fn new(source: &str, offset: usize, kind: ParseErrorKind) -> ParseError {
+        let (line, column) = get_line_column(source, offset);
+        ParseError { offset, line, column, kind }
+    }
+
+    /// Formats the error as the offending line of the source
+    /// followed by a caret under the position and the message
+    pub fn diagnostic(&self, source: &str) -> String {
+        format_diagnostic(source, self.line, self.column, &self.kind)
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::Unexpected { expected } => {
+                write!(f, "expected ")?;
+                for (i, token) in expected.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, "{}", if i + 1 == expected.len() { " or " } else { ", " })?;
+                    }
+                    if token.chars().all(|c| c.is_alphabetic() || c == ' ') {
+                        write!(f, "{}", token)?;
+                    } else {
+                        write!(f, "`{}`", token)?;
+                    }
+                }
+                Ok(())
+            },
+            ParseErrorKind::ArityMismatch { symbol, expected, found } =>
+                write!(f, "function symbol {} is applied to {} argument(s), but was previously used with {}", symbol, found, expected),
+            ParseErrorKind::UnknownSort { sort } => write!(f, "sort {} is not declared", sort),
+            ParseErrorKind::UndeclaredSymbol { symbol } => write!(f, "function symbol {} is not declared", symbol),
+            ParseErrorKind::Redeclaration { name } => write!(f, "{} is already declared", name),
+            ParseErrorKind::ArgumentSortMismatch { symbol, argument, expected, found } =>
+                write!(f, "argument {} of {} has sort {}, but {} is expected", argument, symbol, found, expected),
+            ParseErrorKind::EqualitySortMismatch { left, right } =>
+                write!(f, "equality between terms of sorts {} and {}", left, right),
+            ParseErrorKind::SortMismatch { variable, expected, found } =>
+                write!(f, "variable {} is bound with sort {}, but annotated with {}", variable, expected, found),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+/// Parser for formulas, where function symbols are inferred from their first use, with
+/// all arguments and the result of the default sort, until some sort or function symbol
+/// is declared. From then on, every function symbol has to be declared with its sorts
 pub struct UnsortedParser {
     sort: Rc<Sort>,
-    arity_map: HashMap<String, Rc<FunctionSymbol>>,
+    arity_map: HashMap<String, Rc<FunctionSymbol>>, // inferred function symbols, before any declaration
+    declared_sorts: Vec<Rc<Sort>>,
+    declared_symbols: Vec<Rc<FunctionSymbol>>,
+    /// Variables in scope, with the innermost binder last
+    bound_variables: Vec<(String, Rc<Variable>)>,
+    next_variable_index: VariableIndex,
+    default_sort_used: bool, // whether a variable of the default sort is bound
+    /// The length of the remaining input at the furthest position
+    /// where a token was expected, together with all the tokens expected there
+    furthest_expected: Option<(usize, Vec<&'static str>)>,
 }
 
 impl UnsortedParser {
@@ -47,133 +215,648 @@ impl UnsortedParser {
         UnsortedParser {
             sort: sort.clone(),
             arity_map: HashMap::new(),
+            declared_sorts: vec![],
+            declared_symbols: vec![],
+            bound_variables: vec![],
+            next_variable_index: 0,
+            default_sort_used: false,
+            furthest_expected: None,
         }
     }
 
-    fn identifier<'a>(&self, input: &'a str) -> IResult<&'a str, &'a str> {
-        character::complete::alphanumeric1(input)
+    /// Records that the given token was expected at the start of the remaining input
+    fn expect(&mut self, input: &str, token: &'static str) {
+        match &mut self.furthest_expected {
+            Some((remaining, expected)) if *remaining == input.len() => {
+                if !expected.contains(&token) {
+                    expected.push(token);
+                }
+            },
+            Some((remaining, _)) if *remaining < input.len() => {},
+            _ => self.furthest_expected = Some((input.len(), vec![token])),
+        }
+    }
+
+    /// Parses a token, or its Unicode counterpart, surrounded by optional whitespaces.
+    /// Only the ASCII token is reported as expected
+    fn token<'a>(&mut self, token: &'static str, input: &'a str) -> ParseResult<'a, &'a str> {
+        if let Some((_, symbol)) = UNICODE_TOKENS.iter().find(|(ascii, _)| *ascii == token) {
+            if let IResult::Ok(result) = ws!(tag(*symbol))(input) {
+                return IResult::Ok(result);
+            }
+        }
+        let result = ws!(tag(token))(input);
+        if let IResult::Err(Err::Error(err)) = &result {
+            self.expect(err.input, token);
+        }
+        result
+    }
+
+    fn identifier<'a>(&mut self, input: &'a str) -> ParseResult<'a, &'a str> {
+        let result = character::complete::alphanumeric1(input);
+        if result.is_err() {
+            self.expect(input, "identifier");
+        }
+        result
+    }
+
+    /// Parses a keyword surrounded by optional whitespaces,
+    /// which is not the prefix of a longer identifier
+    fn keyword<'a>(&mut self, keyword: &'static str, input: &'a str) -> ParseResult<'a, &'a str> {
+        let result = ws!(character::complete::alphanumeric1)(input);
+        match result {
+            IResult::Ok((_, word)) if word == keyword => result,
+            _ => {
+                let start = input.trim_start();
+                self.expect(start, keyword);
+                IResult::Err(Err::Error(SyntaxError { input: start, failure: None }))
+            },
+        }
     }
     
-    fn arguments<'a>(&mut self, input: &'a str) -> IResult<&'a str, Vec<Rc<Term>>> {
-        let (input, _) = ws!(tag("("))(input)?;
+    /// Parses a term, together with the input it starts at
+    fn located_term<'a>(&mut self, input: &'a str) -> ParseResult<'a, (&'a str, Rc<Term>)> {
+        let (rest, term) = self.term(input)?;
+        IResult::Ok((rest, (input, term)))
+    }
+
+    fn arguments<'a>(&mut self, input: &'a str) -> ParseResult<'a, Vec<(&'a str, Rc<Term>)>> {
+        let (input, _) = self.token("(", input)?;
         let (input, arguments) = self.terms(input)?;
-        let (input, _) = ws!(tag(")"))(input)?;
+        let (input, _) = self.token(")", input)?;
         IResult::Ok((input, arguments))
     }
     
-    /// Parses a term, a term is either an application "f(<term>, ...)"
-    /// or a constant "a"
-    fn term<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Term>> {
+    /// Parses a term, a term is either an application "f(<term>, ...)",
+    /// a constant "a", or a variable "x" bound by an enclosing quantifier
+    fn term<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Term>> {
+        let start = input;
         let (input, symbol) = self.identifier(input)?;
-        let (input, arguments) = self.arguments(input).or(IResult::Ok((input, vec![])))?;
+
+        // bound names shadow function symbols, and may be annotated
+        // with their sort as in the output of Display
+        if let Some((_, variable)) = self.bound_variables.iter().rev().find(|(name, _)| name == symbol) {
+            let variable = variable.clone();
+            let input = match self.token(":", input) {
+                IResult::Ok((rest, _)) => {
+                    let (rest, sort) = self.sort(rest)?;
+                    if sort != variable.sort {
+                        return failure(start, ParseErrorKind::SortMismatch {
+                            variable: symbol.to_string(),
+                            expected: variable.sort.to_string(),
+                            found: sort.to_string(),
+                        });
+                    }
+                    rest
+                },
+                IResult::Err(_) => input,
+            };
+            return IResult::Ok((input, Rc::new(Term::Variable(variable))));
+        }
+
+        if self.has_declarations() && !self.declared_symbols.iter().any(|declared| declared.get_name() == symbol) {
+            return failure(start, ParseErrorKind::UndeclaredSymbol { symbol: symbol.to_string() });
+        }
+        let (input, arguments) = or_else(self.arguments(input), || IResult::Ok((input, vec![])))?;
     
-        // create a new function symbol
-        let function_symbol = if self.arity_map.contains_key(symbol) {
-            // TODO: better error handling
-            assert!(self.arity_map[symbol].arity() == arguments.len(), "function symbol {} is used with different arities", symbol);
-            self.arity_map[symbol].clone()
+        let declared_symbol = self.declared_symbols.iter().find(|declared| declared.get_name() == symbol);
+        let function_symbol = if let Some(declared_symbol) = declared_symbol.or(self.arity_map.get(symbol)) {
+            let arity = declared_symbol.arity();
+            if arity != arguments.len() {
+                return failure(start, ParseErrorKind::ArityMismatch {
+                    symbol: symbol.to_string(),
+                    expected: arity,
+                    found: arguments.len(),
+                });
+            }
+            declared_symbol.clone()
         } else {
+            // create a new function symbol
             let new_symbol = FunctionSymbol::new(
                 symbol,
-                &iter::repeat(&self.sort).take(arguments.len()).collect::<Vec<&Rc<Sort>>>(), 
+                &iter::repeat_n(&self.sort, arguments.len()).collect::<Vec<&Rc<Sort>>>(),
                 &self.sort,
             );
             self.arity_map.insert(symbol.to_string(), new_symbol.clone());
             new_symbol.clone()
         };
-    
-        IResult::Ok((input, Term::new_application(&function_symbol, &arguments.iter().collect::<Vec<_>>())))
+
+        for (i, ((argument_start, argument), input_sort)) in arguments.iter().zip(function_symbol.get_input_sorts()).enumerate() {
+            if argument.get_sort() != input_sort {
+                return failure(argument_start, ParseErrorKind::ArgumentSortMismatch {
+                    symbol: symbol.to_string(),
+                    argument: i + 1,
+                    expected: input_sort.to_string(),
+                    found: argument.get_sort().to_string(),
+                });
+            }
+        }
+
+        let arguments = arguments.iter().map(|(_, argument)| argument).collect::<Vec<_>>();
+        IResult::Ok((input, Term::new_application(&function_symbol, &arguments)))
     }
     
-    fn terms<'a>(&mut self, input: &'a str) -> IResult<&'a str, Vec<Rc<Term>>> {
-        sep_list!(self, term, ws!(tag(",")), input, false)
+    fn terms<'a>(&mut self, input: &'a str) -> ParseResult<'a, Vec<(&'a str, Rc<Term>)>> {
+        sep_list!(self, located_term, ",", input, false)
     }
     
-    fn equality<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
+    /// Parses "<term> <operator> <term>" where both sides have the same sort
+    fn equation<'a>(&mut self, operator: &'static str, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        let start = input;
         let (input, left) = self.term(input)?;
-        let (input, _) = ws!(tag("="))(input)?;
+        let (input, _) = self.token(operator, input)?;
         let (input, right) = self.term(input)?;
+        if left.get_sort() != right.get_sort() {
+            return failure(start, ParseErrorKind::EqualitySortMismatch {
+                left: left.get_sort().to_string(),
+                right: right.get_sort().to_string(),
+            });
+        }
         IResult::Ok((input, Formula::new_equality(&left, &right)))
     }
 
-    fn neg_equality<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
-        let (input, left) = self.term(input)?;
-        let (input, _) = ws!(tag("!="))(input)?;
-        let (input, right) = self.term(input)?;
-        IResult::Ok((input, Formula::new_negation(&Formula::new_equality(&left, &right))))
+    fn equality<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        self.equation("=", input)
     }
 
-    fn paren_formula<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
-        let (input, _) = ws!(tag("("))(input)?;
+    fn neg_equality<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        let (input, equality) = self.equation("!=", input)?;
+        IResult::Ok((input, Formula::new_negation(&equality)))
+    }
+
+    fn paren_formula<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        let (input, _) = self.token("(", input)?;
         let (input, formula) = self.formula(input)?;
-        let (input, _) = ws!(tag(")"))(input)?;
+        let (input, _) = self.token(")", input)?;
         IResult::Ok((input, formula))
     }
 
-    fn atomic_formula<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
-        self.equality(input).or(self.neg_equality(input)).or(self.paren_formula(input))
+    /// Parses "true" or "⊤", and "false" or "⊥"
+    fn constant<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        let verum = or_else(ws!(tag("⊤"))(input), || self.keyword("true", input));
+        if let IResult::Ok((input, _)) = verum {
+            return IResult::Ok((input, Formula::verum()));
+        }
+        let (input, _) = or_else(ws!(tag("⊥"))(input), || self.keyword("false", input))?;
+        IResult::Ok((input, Formula::falsum()))
+    }
+
+    fn atomic_formula<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        // true and false are tried first, so they are never parsed as constants
+        let result = or_else(self.constant(input), || self.equality(input));
+        let result = or_else(result, || self.neg_equality(input));
+        or_else(result, || self.paren_formula(input))
     }
 
-    fn negation<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
-        let (input, _) = ws!(tag("!"))(input)?;
-        let (input, formula) = self.atomic_formula(input)?;
+    fn negation<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        let (input, _) = self.token("!", input)?;
+        let (input, formula) = self.unary(input)?;
         IResult::Ok((input, Formula::new_negation(&formula)))
     }
 
-    fn unary<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
-        self.negation(input).or(self.atomic_formula(input))
+    /// Parses a binder "<name>" or "<name>: <sort>", where the sort defaults to the
+    /// default sort, and brings a fresh variable for the name into scope
+    fn binder<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Variable>> {
+        let (input, name) = ws!(|input| self.identifier(input))(input)?;
+        let (input, sort) = match self.token(":", input) {
+            IResult::Ok((input, _)) => self.sort(input)?,
+            IResult::Err(_) => (input, self.sort.clone()),
+        };
+
+        self.default_sort_used |= Rc::ptr_eq(&sort, &self.sort);
+        let variable = Rc::new(Variable { index: self.next_variable_index, sort });
+        self.next_variable_index += 1;
+        self.bound_variables.push((name.to_string(), variable.clone()));
+        IResult::Ok((input, variable))
+    }
+
+    fn binders<'a>(&mut self, input: &'a str) -> ParseResult<'a, Vec<Rc<Variable>>> {
+        sep_list!(self, binder, ",", input, true)
+    }
+
+    /// Parses the binders and the body of a quantifier, which is either "." followed
+    /// by a formula extending as far to the right as possible, or a parenthesized formula
+    fn quantifier_body<'a>(&mut self, input: &'a str) -> ParseResult<'a, (Vec<Rc<Variable>>, Rc<Formula>)> {
+        let (input, variables) = self.binders(input)?;
+        let (input, body) = match self.token(".", input) {
+            IResult::Ok((input, _)) => self.formula(input)?,
+            IResult::Err(_) => self.paren_formula(input)?,
+        };
+        IResult::Ok((input, (variables, body)))
+    }
+
+    /// Parses "forall <binder>, ... . <formula>" or "exists <binder>, ... . <formula>",
+    /// also written with "∀" and "∃"
+    fn quantification<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        let universal = or_else(ws!(tag("∀"))(input), || self.keyword("forall", input));
+        let (input, universal) = match universal {
+            IResult::Ok((input, _)) => (input, true),
+            IResult::Err(_) => {
+                let (input, _) = or_else(ws!(tag("∃"))(input), || self.keyword("exists", input))?;
+                (input, false)
+            },
+        };
+
+        // the binders go out of scope after the body, even if it fails to parse
+        let scope = self.bound_variables.len();
+        let result = self.quantifier_body(input);
+        self.bound_variables.truncate(scope);
+        let (input, (variables, body)) = result?;
+
+        let formula = variables.into_iter().rev().fold(body, |body, variable| {
+            if universal {
+                Rc::new(Formula::UniversalQuantification(variable, body))
+            } else {
+                Rc::new(Formula::ExistentialQuantification(variable, body))
+            }
+        });
+        IResult::Ok((input, formula))
+    }
+
+    fn unary<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        let result = or_else(self.negation(input), || self.quantification(input));
+        or_else(result, || self.atomic_formula(input))
     }
 
-    fn conjunction_list<'a>(&mut self, input: &'a str) -> IResult<&'a str, Vec<Rc<Formula>>> {
-        sep_list!(self, unary, ws!(tag("/\\")), input, true)
+    fn conjunction_list<'a>(&mut self, input: &'a str) -> ParseResult<'a, Vec<Rc<Formula>>> {
+        sep_list!(self, unary, "/\\", input, true)
     }
     
-    fn conjunction<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
+    fn conjunction<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
         let (input, conjuncts) = self.conjunction_list(input)?;
         IResult::Ok((input, Formula::new_conjunction(&conjuncts.iter().collect::<Vec<_>>())))
     }
 
-    fn disjunction_list<'a>(&mut self, input: &'a str) -> IResult<&'a str, Vec<Rc<Formula>>> {
-        sep_list!(self, conjunction, ws!(tag("\\/")), input, true)
+    fn disjunction_list<'a>(&mut self, input: &'a str) -> ParseResult<'a, Vec<Rc<Formula>>> {
+        sep_list!(self, conjunction, "\\/", input, true)
     }
 
-    fn disjunction<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
+    fn disjunction<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
         let (input, disjuncts) = self.disjunction_list(input)?;
         IResult::Ok((input, Formula::new_disjunction(&disjuncts.iter().collect::<Vec<_>>())))
     }
 
-    fn implication_or_disjunction<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
+    /// Implication is right-associative, i.e. "a -> b -> c" is "a -> (b -> c)"
+    fn implication_or_disjunction<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
         let (input, left) = self.disjunction(input)?;
-        match ws!(tag("->"))(input) {
+        match self.token("->", input) {
             IResult::Err(_) => IResult::Ok((input, left)),
             IResult::Ok((input, _)) => {
-                let (input, right) = self.disjunction(input)?;
+                let (input, right) = self.implication_or_disjunction(input)?;
                 IResult::Ok((input, Formula::new_implication(&left, &right)))
             }
         }
     }
 
-    fn formula<'a>(&mut self, input: &'a str) -> IResult<&'a str, Rc<Formula>> {
-        self.implication_or_disjunction(input)
+    /// Equivalence is also right-associative
+    fn equivalence_or_implication<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        let (input, left) = self.implication_or_disjunction(input)?;
+        match self.token("<->", input) {
+            IResult::Err(_) => IResult::Ok((input, left)),
+            IResult::Ok((input, _)) => {
+                let (input, right) = self.equivalence_or_implication(input)?;
+                IResult::Ok((input, Formula::new_equivalence(&left, &right)))
+            }
+        }
+    }
+
+    /// Parses a formula, where the connectives bind from the tightest to the loosest:
+    ///   - "!" or "¬", applied to any unary formula
+    ///   - "/\" or "∧"
+    ///   - "\/" or "∨"
+    ///   - "->" or "→", right-associative
+    ///   - "<->" or "⇔", right-associative
+    ///
+    /// Quantifiers "forall x. <formula>" and "exists x. <formula>" bind looser than all of them,
+    /// and the atomic formulas are "true" or "⊤", "false" or "⊥",
+    /// equalities "s = t", disequalities "s != t" and parenthesized formulas
+    fn formula<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Formula>> {
+        self.equivalence_or_implication(input)
     }
 
-    pub fn parse_term(&mut self, input: &str) -> Option<Rc<Term>> {
-        match self.term(input) {
-            IResult::Ok((_, term)) => Some(term.clone()),
-            _ => None,
+    /// Parses the name of a declared sort
+    fn sort<'a>(&mut self, input: &'a str) -> ParseResult<'a, Rc<Sort>> {
+        let (rest, name) = ws!(|input| self.identifier(input))(input)?;
+        match iter::once(&self.sort).chain(&self.declared_sorts).find(|sort| sort.get_name() == name) {
+            Some(sort) => IResult::Ok((rest, sort.clone())),
+            None => failure(input.trim_start(), ParseErrorKind::UnknownSort { sort: name.to_string() }),
         }
     }
 
-    /// Parse a quantifier free formula with terms looking like f(a, f(a, b))
-    pub fn parse_formula<'a>(&mut self, input: &'a str) -> Option<(&'a str, Rc<Formula>)> {
-        match self.formula(input) {
-            IResult::Ok((input, formula)) => Some((input, formula.clone())),
-            _ => None,
+    /// Parses a name that is not declared yet
+    fn new_name<'a>(&mut self, input: &'a str) -> ParseResult<'a, &'a str> {
+        let (rest, name) = ws!(|input| self.identifier(input))(input)?;
+        let declared = iter::once(&self.sort).chain(&self.declared_sorts).any(|sort| sort.get_name() == name) ||
+            self.declared_symbols.iter().any(|symbol| symbol.get_name() == name);
+        if declared {
+            return failure(input.trim_start(), ParseErrorKind::Redeclaration { name: name.to_string() });
         }
+        IResult::Ok((rest, name))
     }
 
-    /// Return the language containing all function symbols currently constructed
+    /// Parses a declaration "sort <name>;", "fun <name>: <sort> ... -> <sort>;"
+    /// or "const <name>: <sort>;", and adds it to the language
+    fn declaration<'a>(&mut self, input: &'a str) -> ParseResult<'a, Declaration> {
+        if let IResult::Ok((input, _)) = self.keyword("sort", input) {
+            let (input, name) = self.new_name(input)?;
+            let (input, _) = self.token(";", input)?;
+            let sort = Sort::new(name);
+            self.declared_sorts.push(sort.clone());
+            self.arity_map.clear();
+            return IResult::Ok((input, Declaration::Sort(sort)));
+        }
+
+        let (input, input_sorts) = match self.keyword("fun", input) {
+            IResult::Ok((input, _)) => {
+                let (input, name) = self.new_name(input)?;
+                let (input, _) = self.token(":", input)?;
+                let mut input = input;
+                let mut input_sorts = vec![];
+                while let IResult::Err(Err::Error(_)) = self.token("->", input) {
+                    let (rest, sort) = self.sort(input)?;
+                    input = rest;
+                    input_sorts.push(sort);
+                }
+                let (input, _) = self.token("->", input)?;
+                ((input, name), input_sorts)
+            },
+            IResult::Err(_) => {
+                let (input, _) = self.keyword("const", input)?;
+                let (input, name) = self.new_name(input)?;
+                let (input, _) = self.token(":", input)?;
+                ((input, name), vec![])
+            },
+        };
+        let (input, name) = input;
+        let (input, output_sort) = self.sort(input)?;
+        let (input, _) = self.token(";", input)?;
+
+        let symbol = FunctionSymbol::new(name, &input_sorts.iter().collect::<Vec<_>>(), &output_sort);
+        // symbols are no longer inferred once something is declared
+        self.arity_map.clear();
+        self.declared_symbols.push(symbol.clone());
+        IResult::Ok((input, Declaration::Function(symbol)))
+    }
+
+    fn statement<'a>(&mut self, input: &'a str) -> ParseResult<'a, Statement> {
+        match self.declaration(input) {
+            IResult::Ok((input, declaration)) => {
+                let mut input = input;
+                let mut declarations = vec![declaration];
+                while !input.trim().is_empty() {
+                    let (rest, declaration) = self.declaration(input)?;
+                    input = rest;
+                    declarations.push(declaration);
+                }
+                IResult::Ok((input, Statement::Declarations(declarations)))
+            },
+            IResult::Err(Err::Error(_)) => {
+                let (input, formula) = self.formula(input)?;
+                IResult::Ok((input, Statement::Formula(formula)))
+            },
+            IResult::Err(err) => IResult::Err(err),
+        }
+    }
+
+    /// Runs the parser on the whole input, which may only be followed by whitespaces
+    fn parse_all<'a, T>(
+        &mut self,
+        input: &'a str,
+        parser: impl FnOnce(&mut Self, &'a str) -> ParseResult<'a, T>,
+    ) -> Result<T, ParseError> {
+        self.furthest_expected = None;
+        self.bound_variables.clear();
+        self.next_variable_index = 0;
+
+        let failure = match parser(self, input) {
+            IResult::Ok((rest, result)) => {
+                let rest = rest.trim_start();
+                if rest.is_empty() {
+                    return Ok(result);
+                }
+                self.expect(rest, "end of input");
+                None
+            },
+            IResult::Err(Err::Failure(SyntaxError { input: rest, failure: Some(kind) })) => Some((rest, kind)),
+            IResult::Err(_) => None,
+        };
+
+        let (offset, kind) = match failure {
+            Some((rest, kind)) => (input.len() - rest.len(), kind),
+            None => {
+                let (remaining, expected) = self.furthest_expected.take().unwrap_or((0, vec![]));
+                (input.len() - remaining, ParseErrorKind::Unexpected { expected })
+            },
+        };
+
+        Err(ParseError::new(input, offset, kind))
+    }
+
+    pub fn parse_term(&mut self, input: &str) -> Result<Rc<Term>, ParseError> {
+        self.parse_all(input, |parser, input| parser.term(input))
+    }
+
+    /// Parse a formula, possibly with quantifiers, with terms looking like f(a, f(a, b))
+    pub fn parse_formula(&mut self, input: &str) -> Result<Rc<Formula>, ParseError> {
+        self.parse_all(input, |parser, input| parser.formula(input))
+    }
+
+    /// Parse either a list of declarations, which are kept for the following inputs,
+    /// or a formula
+    pub fn parse_statement(&mut self, input: &str) -> Result<Statement, ParseError> {
+        self.parse_all(input, |parser, input| parser.statement(input))
+    }
+
+    /// Check if some sort or function symbol is declared, in which case
+    /// undeclared symbols are errors instead of being inferred
+    pub fn has_declarations(&self) -> bool {
+        !self.declared_sorts.is_empty() || !self.declared_symbols.is_empty()
+    }
+
+    /// Forget the function symbols inferred from the inputs so far
+    pub fn forget_inferred_symbols(&mut self) {
+        self.arity_map.clear();
+        self.default_sort_used = false;
+    }
+
+    /// Return the language containing the declared sorts and symbols and all
+    /// function symbols currently inferred. The default sort is only included
+    /// if some symbol or variable uses it, or no sort is declared
     pub fn get_language(&self) -> Rc<Language> {
-        Language::new(&[&self.sort], &self.arity_map.values().collect::<Vec<_>>(), &[])
+        let mut sorts = self.declared_sorts.iter().collect::<Vec<_>>();
+        let uses_default_sort = self.default_sort_used || self.declared_symbols.iter().any(|symbol| {
+            symbol.get_output_sort() == &self.sort || symbol.get_input_sorts().contains(&self.sort)
+        });
+        if !self.arity_map.is_empty() || uses_default_sort || sorts.is_empty() {
+            sorts.insert(0, &self.sort);
+        }
+        let mut function_symbols = self.declared_symbols.iter().collect::<Vec<_>>();
+        function_symbols.extend(self.arity_map.values());
+        Language::new(&sorts, &function_symbols, &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(input: &str) -> ParseError {
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+        parser.parse_formula(input).unwrap_err()
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = parse_error(r"a = b c");
+        assert_eq!((error.offset, error.line, error.column), (6, 1, 7));
+        assert_eq!(error.kind, ParseErrorKind::Unexpected { expected: vec!["(", "/\\", "\\/", "->", "<->", "end of input"] });
+        assert_eq!(error.to_string(), r"1:7: expected `(`, `/\`, `\/`, `->`, `<->` or end of input");
+
+        let error = parse_error("a = b /\\\n  (c = d \\/ f(a) = f(b, c))");
+        assert_eq!((error.offset, error.line, error.column), (28, 2, 20));
+        assert_eq!(error.kind, ParseErrorKind::ArityMismatch { symbol: "f".to_string(), expected: 1, found: 2 });
+        assert_eq!(
+            error.diagnostic("a = b /\\\n  (c = d \\/ f(a) = f(b, c))"),
+            "  (c = d \\/ f(a) = f(b, c))\n                   ^ function symbol f is applied to 2 argument(s), but was previously used with 1",
+        );
+
+        let error = parse_error(r"(a = b");
+        assert_eq!(error.column, 7);
+        assert_eq!(error.kind, ParseErrorKind::Unexpected { expected: vec!["(", "/\\", "\\/", "->", "<->", ")"] });
+
+        let error = parse_error(r"a = f(b,)");
+        assert_eq!(error.column, 9);
+        assert_eq!(error.kind, ParseErrorKind::Unexpected { expected: vec!["identifier"] });
+    }
+
+    #[test]
+    fn declarations() {
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+
+        // symbols are inferred until the first declaration
+        parser.parse_formula("g(c) = c").unwrap();
+        assert!(!parser.has_declarations());
+
+        match parser.parse_statement("sort Node; fun next: Node -> Node; const nil: Node;").unwrap() {
+            Statement::Declarations(declarations) => assert_eq!(declarations.len(), 3),
+            Statement::Formula(formula) => panic!("unexpected formula {}", formula),
+        }
+
+        let error = parser.parse_formula("next(nil) = nil /\\ g(c) = c").unwrap_err();
+        assert_eq!(error.column, 20);
+        assert_eq!(error.kind, ParseErrorKind::UndeclaredSymbol { symbol: "g".to_string() });
+        assert_eq!(error.to_string(), "1:20: function symbol g is not declared");
+
+        parser.parse_statement("const a: A; const b: A; fun f: A A -> A;").unwrap();
+
+        let formula = match parser.parse_statement("next(nil) = nil /\\ a = b").unwrap() {
+            Statement::Formula(formula) => formula,
+            Statement::Declarations(..) => panic!("unexpected declarations"),
+        };
+        let language = parser.get_language();
+        assert_eq!(language.iter_sorts().count(), 2);
+        assert!(language.check_formula(&formula).is_empty());
+
+        // terms of sort Node are used where the default sort is expected and vice versa
+        let error = parser.parse_formula("a = b /\\ next(nil) = a").unwrap_err();
+        assert_eq!(error.column, 10);
+        assert_eq!(error.kind, ParseErrorKind::EqualitySortMismatch { left: "Node".to_string(), right: "A".to_string() });
+
+        let error = parser.parse_formula("next(next(a)) != nil").unwrap_err();
+        assert_eq!(error.column, 11);
+        assert_eq!(error.kind, ParseErrorKind::ArgumentSortMismatch {
+            symbol: "next".to_string(),
+            argument: 1,
+            expected: "Node".to_string(),
+            found: "A".to_string(),
+        });
+        assert_eq!(error.to_string(), "1:11: argument 1 of next has sort A, but Node is expected");
+
+        let error = parser.parse_formula("f(a, nil) = a").unwrap_err();
+        assert_eq!(error.column, 6);
+        assert_eq!(error.kind, ParseErrorKind::ArgumentSortMismatch {
+            symbol: "f".to_string(),
+            argument: 2,
+            expected: "A".to_string(),
+            found: "Node".to_string(),
+        });
+
+        let error = parser.parse_formula("next(nil, nil) = nil").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::ArityMismatch { symbol: "next".to_string(), expected: 1, found: 2 });
+
+        let error = parser.parse_statement("fun h: Node List -> Node;").unwrap_err();
+        assert_eq!(error.column, 13);
+        assert_eq!(error.kind, ParseErrorKind::UnknownSort { sort: "List".to_string() });
+
+        let error = parser.parse_statement("sort A; const nil: A;").unwrap_err();
+        assert_eq!(error.column, 6);
+        assert_eq!(error.kind, ParseErrorKind::Redeclaration { name: "A".to_string() });
+    }
+
+    #[test]
+    fn connectives() {
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+
+        let formula = parser.parse_formula(r"a = b -> b = c -> !!a = c").unwrap();
+        assert_eq!(formula.to_string(), "(a() = b() → (b() = c() → ¬(¬(a() = c()))))");
+
+        let formula = parser.parse_formula(r"a = b \/ b = c /\ true <-> !(false)").unwrap();
+        assert_eq!(formula.to_string(), "((a() = b() ∨ (b() = c() ∧ ⊤)) ⇔ ¬(⊥))");
+
+        // printed formulas parse back to the same formula
+        for input in [
+            r"!(a = b /\ b = c -> f(a) = f(c))",
+            r"(a = b <-> b = a) \/ false /\ !true",
+            r"a != b <-> b != c <-> a = c",
+        ] {
+            let printed = parser.parse_formula(input).unwrap().to_string();
+            assert_eq!(parser.parse_formula(&printed).unwrap().to_string(), printed);
+        }
+    }
+
+    #[test]
+    fn quantifiers() {
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+
+        let formula = parser.parse_formula(r"forall x. f(x) = x /\ exists y, z. f(y) != z").unwrap();
+        assert_eq!(formula.to_string(), "∀x0:A ((f(x0:A) = x0:A ∧ ∃x1:A (∃x2:A (¬(f(x1:A) = x2:A)))))");
+        assert!(formula.get_free_variables().is_empty());
+        assert_eq!(parser.get_language().iter_function_symbols().count(), 1);
+
+        // the body extends to the right, and inner binders shadow outer ones
+        let formula = parser.parse_formula(r"a = b -> ∀x. ∃x. x = a \/ x = b").unwrap();
+        assert_eq!(formula.to_string(), "(a() = b() → ∀x0:A (∃x1:A ((x1:A = a() ∨ x1:A = b()))))");
+
+        // names are only bound inside the body
+        let formula = parser.parse_formula(r"(forall x. x = c) /\ x = c").unwrap();
+        assert_eq!(formula.to_string(), "(∀x0:A (x0:A = c()) ∧ x() = c())");
+
+        // printed formulas parse back to the same formula
+        let printed = parser.parse_formula(r"forall x. exists y. f(x) = y").unwrap().to_string();
+        assert_eq!(parser.parse_formula(&printed).unwrap().to_string(), printed);
+
+        let error = parser.parse_formula("forall x y. x = y").unwrap_err();
+        assert_eq!(error.column, 10);
+        assert_eq!(error.kind, ParseErrorKind::Unexpected { expected: vec![":", ",", ".", "("] });
+
+        parser.parse_statement("sort Node; fun next: Node -> Node;").unwrap();
+        parser.forget_inferred_symbols();
+        let formula = parser.parse_formula("forall n: Node. next(n) != n").unwrap();
+        assert!(parser.get_language().check_formula(&formula).is_empty());
+
+        let error = parser.parse_formula("forall n: List. next(n) != n").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnknownSort { sort: "List".to_string() });
+
+        let error = parser.parse_formula("forall x. exists n: Node. x:Node = x").unwrap_err();
+        assert_eq!(error.column, 27);
+        assert_eq!(error.kind, ParseErrorKind::SortMismatch {
+            variable: "x".to_string(),
+            expected: "A".to_string(),
+            found: "Node".to_string(),
+        });
     }
 }
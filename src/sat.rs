@@ -0,0 +1,681 @@
+//! A CDCL search procedure for propositional clauses modulo a theory
+
+use std::ops::Not;
+use std::fmt;
+
+pub type Var = usize;
+
+/// A propositional variable or its negation
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Lit(usize);
+
+impl Lit {
+    pub fn new(var: Var, negated: bool) -> Lit {
+        Lit(2 * var + negated as usize)
+    }
+
+    pub fn get_var(self) -> Var {
+        self.0 / 2
+    }
+
+    pub fn is_negated(self) -> bool {
+        self.0 % 2 == 1
+    }
+}
+
+impl Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+impl fmt::Display for Lit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_negated() {
+            write!(f, "¬p{}", self.get_var())
+        } else {
+            write!(f, "p{}", self.get_var())
+        }
+    }
+}
+
+/// A decision procedure for conjunctions of theory literals,
+/// which are notified of the literals assigned by the search
+pub trait Theory {
+    /// Add a literal assigned by the search. If the assigned literals become
+    /// inconsistent, return a conflict clause, i.e. a clause implied by the
+    /// theory whose literals are all false under the current assignment
+    fn assert_literal(&mut self, lit: Lit) -> Result<(), Vec<Lit>>;
+
+    /// Save the current state when the search opens a new decision level
+    fn push(&mut self);
+
+    /// Restore the state saved by the n-th most recent push
+    fn pop(&mut self, n: usize);
+}
+
+pub enum SearchResult {
+    Sat,
+    Unsat,
+}
+
+/// Binary max-heap of variables ordered by activity
+struct VarOrder {
+    heap: Vec<Var>,
+    positions: Vec<Option<usize>>,
+}
+
+impl VarOrder {
+    fn new() -> VarOrder {
+        VarOrder { heap: vec![], positions: vec![] }
+    }
+
+    fn insert(&mut self, var: Var, activity: &[f64]) {
+        if self.positions.len() <= var {
+            self.positions.resize(var + 1, None);
+        }
+        if self.positions[var].is_none() {
+            self.heap.push(var);
+            self.positions[var] = Some(self.heap.len() - 1);
+            self.sift_up(self.heap.len() - 1, activity);
+        }
+    }
+
+    /// Restore the heap order after the activity of var has increased
+    fn increase(&mut self, var: Var, activity: &[f64]) {
+        if let Some(Some(position)) = self.positions.get(var) {
+            self.sift_up(*position, activity);
+        }
+    }
+
+    fn pop_max(&mut self, activity: &[f64]) -> Option<Var> {
+        if self.heap.is_empty() {
+            return None;
+        }
+
+        let max = self.heap.swap_remove(0);
+        self.positions[max] = None;
+        if !self.heap.is_empty() {
+            self.positions[self.heap[0]] = Some(0);
+            self.sift_down(0, activity);
+        }
+        Some(max)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        self.positions[self.heap[i]] = Some(i);
+        self.positions[self.heap[j]] = Some(j);
+    }
+
+    fn sift_up(&mut self, mut i: usize, activity: &[f64]) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if activity[self.heap[parent]] >= activity[self.heap[i]] {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize, activity: &[f64]) {
+        loop {
+            let mut largest = i;
+            for child in [2 * i + 1, 2 * i + 2] {
+                if child < self.heap.len() && activity[self.heap[child]] > activity[self.heap[largest]] {
+                    largest = child;
+                }
+            }
+            if largest == i {
+                break;
+            }
+            self.swap(i, largest);
+            i = largest;
+        }
+    }
+}
+
+/// Conflict-driven clause learning over clauses of literals, with a theory
+/// solver checking the consistency of each partial assignment (CDCL(T))
+pub struct CDCLSolver<T: Theory> {
+    pub theory: T,
+
+    clauses: Vec<Vec<Lit>>, // input and learned clauses with at least two literals
+    watches: Vec<Vec<usize>>, // for each literal, the clauses watching it
+
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>, // decision level of each assigned variable
+    reasons: Vec<Option<usize>>, // clause that propagated each assigned variable
+
+    trail: Vec<Lit>, // assigned literals in order
+    trail_limits: Vec<usize>, // trail length at the start of each decision level
+    propagated: usize, // number of trail literals propagated through the clauses
+    theory_asserted: usize, // number of trail literals added to the theory
+
+    activity: Vec<f64>,
+    activity_increment: f64,
+    order: VarOrder,
+    phases: Vec<bool>, // last value of each variable
+    seen: Vec<bool>, // scratch space for conflict analysis
+
+    inconsistent: bool, // the empty clause has been derived
+    failed_assumptions: Vec<Lit>,
+}
+
+impl<T: Theory> CDCLSolver<T> {
+    pub fn new(theory: T) -> CDCLSolver<T> {
+        CDCLSolver {
+            theory,
+            clauses: vec![],
+            watches: vec![],
+            values: vec![],
+            levels: vec![],
+            reasons: vec![],
+            trail: vec![],
+            trail_limits: vec![],
+            propagated: 0,
+            theory_asserted: 0,
+            activity: vec![],
+            activity_increment: 1.0,
+            order: VarOrder::new(),
+            phases: vec![],
+            seen: vec![],
+            inconsistent: false,
+            failed_assumptions: vec![],
+        }
+    }
+
+    /// Create a fresh variable
+    pub fn new_var(&mut self) -> Var {
+        let var = self.values.len();
+        self.watches.push(vec![]);
+        self.watches.push(vec![]);
+        self.values.push(None);
+        self.levels.push(0);
+        self.reasons.push(None);
+        self.activity.push(0.0);
+        self.phases.push(false);
+        self.seen.push(false);
+        self.order.insert(var, &self.activity);
+        var
+    }
+
+    pub fn get_num_vars(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Get the number of input and learned clauses with at least two literals
+    pub fn get_num_clauses(&self) -> usize {
+        self.clauses.len()
+    }
+
+    /// Get the value of a variable in the assignment found by the last search
+    pub fn get_value(&self, var: Var) -> Option<bool> {
+        self.values[var]
+    }
+
+    /// Get the value of a literal in the assignment found by the last search
+    pub fn get_lit_value(&self, lit: Lit) -> Option<bool> {
+        self.values[lit.get_var()].map(|value| value != lit.is_negated())
+    }
+
+    /// After an unsat search, get the assumptions that are
+    /// inconsistent with the clauses. Empty if the clauses
+    /// alone are unsatisfiable
+    pub fn get_failed_assumptions(&self) -> &[Lit] {
+        &self.failed_assumptions
+    }
+
+    /// Undo the assignment found by the last search, keeping the
+    /// assignment at level 0, so that the theory only holds permanent facts
+    pub fn reset(&mut self) {
+        self.backtrack(0);
+    }
+
+    /// Add a clause, i.e. a disjunction of literals
+    pub fn add_clause(&mut self, clause: &[Lit]) {
+        self.backtrack(0);
+        if self.inconsistent {
+            return;
+        }
+
+        let mut clause = clause.to_vec();
+        clause.sort();
+        clause.dedup();
+
+        // a literal and its negation are adjacent after sorting
+        if clause.windows(2).any(|pair| pair[0] == !pair[1]) {
+            return;
+        }
+
+        // simplify with respect to the assignment at level 0
+        if clause.iter().any(|lit| self.get_lit_value(*lit) == Some(true)) {
+            return;
+        }
+        clause.retain(|lit| self.get_lit_value(*lit).is_none());
+
+        match clause.len() {
+            0 => self.inconsistent = true,
+            1 => self.enqueue(clause[0], None),
+            _ => {
+                self.attach_clause(clause);
+            },
+        }
+    }
+
+    /// Search for an assignment satisfying all clauses and the theory
+    /// in which all of the given assumptions are true
+    pub fn solve(&mut self, assumptions: &[Lit]) -> SearchResult {
+        self.failed_assumptions.clear();
+        self.backtrack(0);
+
+        let mut num_conflicts = 0;
+        let mut restart_limit = 100;
+
+        loop {
+            if self.inconsistent {
+                return SearchResult::Unsat;
+            }
+
+            if let Some(conflict) = self.propagate() {
+                let conflict_level = conflict.iter().map(|lit| self.levels[lit.get_var()]).max().unwrap_or(0);
+                if conflict_level == 0 {
+                    self.inconsistent = true;
+                    return SearchResult::Unsat;
+                }
+
+                // theory conflicts may only involve earlier levels
+                self.backtrack(conflict_level);
+
+                let (learned, backjump_level) = self.analyze(&conflict);
+                self.backtrack(backjump_level);
+
+                if learned.len() == 1 {
+                    self.enqueue(learned[0], None);
+                } else {
+                    let asserting_lit = learned[0];
+                    let clause_index = self.attach_clause(learned);
+                    self.enqueue(asserting_lit, Some(clause_index));
+                }
+                self.activity_increment /= 0.95;
+
+                num_conflicts += 1;
+                if num_conflicts >= restart_limit {
+                    num_conflicts = 0;
+                    restart_limit += restart_limit / 2;
+                    self.backtrack(0);
+                }
+
+                continue;
+            }
+
+            // decide the assumptions first, one per level
+            let level = self.get_level();
+            if level < assumptions.len() {
+                let assumption = assumptions[level];
+                match self.get_lit_value(assumption) {
+                    Some(true) => self.new_level(),
+                    Some(false) => {
+                        self.failed_assumptions = self.analyze_final(assumption);
+                        return SearchResult::Unsat;
+                    },
+                    None => {
+                        self.new_level();
+                        self.enqueue(assumption, None);
+                    },
+                }
+                continue;
+            }
+
+            match self.pick_branching_var() {
+                Some(var) => {
+                    self.new_level();
+                    self.enqueue(Lit::new(var, !self.phases[var]), None);
+                },
+                None => return SearchResult::Sat,
+            }
+        }
+    }
+
+    fn get_level(&self) -> usize {
+        self.trail_limits.len()
+    }
+
+    fn new_level(&mut self) {
+        self.trail_limits.push(self.trail.len());
+        self.theory.push();
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.get_var();
+        debug_assert!(self.values[var].is_none());
+        self.values[var] = Some(!lit.is_negated());
+        self.levels[var] = self.get_level();
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Add a clause with at least two literals, watching the first two
+    fn attach_clause(&mut self, clause: Vec<Lit>) -> usize {
+        let clause_index = self.clauses.len();
+        self.watches[clause[0].0].push(clause_index);
+        self.watches[clause[1].0].push(clause_index);
+        self.clauses.push(clause);
+        clause_index
+    }
+
+    /// Undo all assignments above the given level
+    fn backtrack(&mut self, level: usize) {
+        if self.get_level() <= level {
+            return;
+        }
+
+        let limit = self.trail_limits[level];
+        for lit in self.trail.drain(limit..) {
+            let var = lit.get_var();
+            self.values[var] = None;
+            self.reasons[var] = None;
+            self.phases[var] = !lit.is_negated();
+            self.order.insert(var, &self.activity);
+        }
+
+        self.theory.pop(self.trail_limits.len() - level);
+        self.trail_limits.truncate(level);
+        self.propagated = self.propagated.min(limit);
+        self.theory_asserted = self.theory_asserted.min(limit);
+    }
+
+    /// Propagate the assigned literals through the clauses and the theory.
+    /// Returns a clause falsified by the current assignment if there is one
+    fn propagate(&mut self) -> Option<Vec<Lit>> {
+        loop {
+            if let Some(clause_index) = self.propagate_clauses() {
+                return Some(self.clauses[clause_index].clone());
+            }
+
+            if self.theory_asserted == self.trail.len() {
+                return None;
+            }
+
+            while self.theory_asserted < self.trail.len() {
+                let lit = self.trail[self.theory_asserted];
+                self.theory_asserted += 1;
+                if let Err(conflict) = self.theory.assert_literal(lit) {
+                    return Some(conflict);
+                }
+            }
+        }
+    }
+
+    /// Unit propagation with two watched literals per clause.
+    /// Returns the index of a falsified clause if there is one
+    fn propagate_clauses(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let false_lit = !self.trail[self.propagated];
+            self.propagated += 1;
+
+            let watchers = std::mem::take(&mut self.watches[false_lit.0]);
+            let mut kept = Vec::with_capacity(watchers.len());
+            let mut conflict = None;
+
+            for clause_index in watchers {
+                if conflict.is_some() {
+                    kept.push(clause_index);
+                    continue;
+                }
+
+                // make sure the false literal is the second watch
+                let clause = &mut self.clauses[clause_index];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+
+                let first = clause[0];
+                if self.values[first.get_var()].map(|value| value != first.is_negated()) == Some(true) {
+                    kept.push(clause_index);
+                    continue;
+                }
+
+                // look for another literal that is not false
+                let values = &self.values;
+                let replacement = (2..clause.len()).find(|i| {
+                    let lit = clause[*i];
+                    values[lit.get_var()].map(|value| value != lit.is_negated()) != Some(false)
+                });
+
+                match replacement {
+                    Some(i) => {
+                        clause.swap(1, i);
+                        self.watches[clause[1].0].push(clause_index);
+                    },
+                    None => {
+                        kept.push(clause_index);
+                        if self.get_lit_value(first) == Some(false) {
+                            conflict = Some(clause_index);
+                        } else {
+                            self.enqueue(first, Some(clause_index));
+                        }
+                    },
+                }
+            }
+
+            self.watches[false_lit.0] = kept;
+
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+
+        None
+    }
+
+    /// Derive a clause from a conflict by resolving with reasons until a single
+    /// literal of the current level remains (first unique implication point).
+    /// Returns the learned clause, with the asserting literal first and a literal
+    /// of the highest remaining level second, together with the level to backjump to
+    fn analyze(&mut self, conflict: &[Lit]) -> (Vec<Lit>, usize) {
+        let level = self.get_level();
+        let mut learned = vec![conflict[0]]; // the first literal is a placeholder
+        let mut num_current_level = 0;
+        let mut clause = conflict.to_vec();
+        let mut resolved_var = None;
+        let mut index = self.trail.len();
+
+        loop {
+            for lit in &clause {
+                let var = lit.get_var();
+                if Some(var) == resolved_var || self.seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+
+                self.seen[var] = true;
+                self.bump_activity(var);
+
+                if self.levels[var] == level {
+                    num_current_level += 1;
+                } else {
+                    learned.push(*lit);
+                }
+            }
+
+            // find the latest assigned literal involved in the conflict
+            loop {
+                index -= 1;
+                if self.seen[self.trail[index].get_var()] {
+                    break;
+                }
+            }
+
+            let lit = self.trail[index];
+            let var = lit.get_var();
+            self.seen[var] = false;
+            num_current_level -= 1;
+
+            if num_current_level == 0 {
+                learned[0] = !lit;
+                break;
+            }
+
+            resolved_var = Some(var);
+            clause = self.clauses[self.reasons[var].expect("literal without a reason")].clone();
+        }
+
+        for lit in &learned[1..] {
+            self.seen[lit.get_var()] = false;
+        }
+
+        // watch a literal of the highest level among the rest
+        let mut backjump_level = 0;
+        if learned.len() > 1 {
+            let mut max_index = 1;
+            for i in 2..learned.len() {
+                if self.levels[learned[i].get_var()] > self.levels[learned[max_index].get_var()] {
+                    max_index = i;
+                }
+            }
+            learned.swap(1, max_index);
+            backjump_level = self.levels[learned[1].get_var()];
+        }
+
+        (learned, backjump_level)
+    }
+
+    /// Find the assumptions that imply the negation of the given assumption
+    fn analyze_final(&mut self, assumption: Lit) -> Vec<Lit> {
+        let mut failed = vec![assumption];
+        let var = assumption.get_var();
+        if self.levels[var] == 0 {
+            return failed;
+        }
+
+        self.seen[var] = true;
+        for i in (self.trail_limits[0]..self.trail.len()).rev() {
+            let lit = self.trail[i];
+            let var = lit.get_var();
+            if !self.seen[var] {
+                continue;
+            }
+
+            match self.reasons[var] {
+                // decisions below the assumption levels are assumptions
+                None => failed.push(lit),
+                Some(clause_index) => {
+                    for other in &self.clauses[clause_index] {
+                        if self.levels[other.get_var()] > 0 {
+                            self.seen[other.get_var()] = true;
+                        }
+                    }
+                },
+            }
+            self.seen[var] = false;
+        }
+
+        failed
+    }
+
+    fn bump_activity(&mut self, var: Var) {
+        self.activity[var] += self.activity_increment;
+        if self.activity[var] > 1e100 {
+            for activity in &mut self.activity {
+                *activity *= 1e-100;
+            }
+            self.activity_increment *= 1e-100;
+        }
+        self.order.increase(var, &self.activity);
+    }
+
+    fn pick_branching_var(&mut self) -> Option<Var> {
+        while let Some(var) = self.order.pop_max(&self.activity) {
+            if self.values[var].is_none() {
+                return Some(var);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Theory that accepts every assignment
+    struct NoTheory;
+
+    impl Theory for NoTheory {
+        fn assert_literal(&mut self, _: Lit) -> Result<(), Vec<Lit>> {
+            Ok(())
+        }
+
+        fn push(&mut self) {}
+
+        fn pop(&mut self, _: usize) {}
+    }
+
+    /// Pigeonhole principle: n + 1 pigeons do not fit into n holes
+    fn add_pigeonhole(search: &mut CDCLSolver<NoTheory>, n: usize) {
+        let vars = (0..(n + 1) * n).map(|_| search.new_var()).collect::<Vec<_>>();
+        let in_hole = |pigeon: usize, hole: usize| vars[pigeon * n + hole];
+
+        for pigeon in 0..n + 1 {
+            search.add_clause(&(0..n).map(|hole| Lit::new(in_hole(pigeon, hole), false)).collect::<Vec<_>>());
+        }
+
+        for hole in 0..n {
+            for pigeon1 in 0..n + 1 {
+                for pigeon2 in 0..pigeon1 {
+                    search.add_clause(&[Lit::new(in_hole(pigeon1, hole), true), Lit::new(in_hole(pigeon2, hole), true)]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pigeonhole_is_unsat() {
+        let mut search = CDCLSolver::new(NoTheory);
+        add_pigeonhole(&mut search, 5);
+        assert!(matches!(search.solve(&[]), SearchResult::Unsat));
+    }
+
+    #[test]
+    fn satisfying_assignment() {
+        // (x0 \/ x1) /\ (~x0 \/ x2) /\ (~x1 \/ ~x2) /\ (~x2 \/ x0)
+        let mut search = CDCLSolver::new(NoTheory);
+        let x = (0..3).map(|_| search.new_var()).collect::<Vec<_>>();
+        let clauses = [
+            [Lit::new(x[0], false), Lit::new(x[1], false)],
+            [Lit::new(x[0], true), Lit::new(x[2], false)],
+            [Lit::new(x[1], true), Lit::new(x[2], true)],
+            [Lit::new(x[2], true), Lit::new(x[0], false)],
+        ];
+        for clause in &clauses {
+            search.add_clause(clause);
+        }
+
+        assert!(matches!(search.solve(&[]), SearchResult::Sat));
+        for clause in &clauses {
+            assert!(clause.iter().any(|lit| search.get_lit_value(*lit) == Some(true)));
+        }
+    }
+
+    #[test]
+    fn failed_assumptions() {
+        // x0 -> x1, x1 -> x2
+        let mut search = CDCLSolver::new(NoTheory);
+        let x = (0..4).map(|_| search.new_var()).collect::<Vec<_>>();
+        search.add_clause(&[Lit::new(x[0], true), Lit::new(x[1], false)]);
+        search.add_clause(&[Lit::new(x[1], true), Lit::new(x[2], false)]);
+
+        let assumptions = [Lit::new(x[0], false), Lit::new(x[3], false), Lit::new(x[2], true)];
+        assert!(matches!(search.solve(&assumptions), SearchResult::Unsat));
+
+        let mut failed = search.get_failed_assumptions().to_vec();
+        failed.sort();
+        assert_eq!(failed, vec![Lit::new(x[0], false), Lit::new(x[2], true)]);
+
+        // the clauses alone are still satisfiable
+        assert!(matches!(search.solve(&assumptions[..2]), SearchResult::Sat));
+    }
+}
@@ -0,0 +1,1004 @@
+//! Reader and executor for SMT-LIB 2 scripts in the QF_UF logic
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::fmt;
+
+use crate::fol::*;
+use crate::parser::{format_diagnostic, get_line_column};
+use crate::solver::*;
+
+/// An error in an SMT-LIB script at a byte offset of the input,
+/// with the corresponding line and column (both starting from 1)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SmtLibError {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// A command of an SMT-LIB script, with the sorts and symbols resolved.
+/// A function declared with the result sort Bool is a relation symbol
+#[derive(Debug)]
+pub enum Command {
+    SetLogic(String),
+    DeclareSort(Rc<Sort>),
+    DeclareFunction(Rc<FunctionSymbol>),
+    DeclareRelation(Rc<RelationSymbol>),
+    Assert(Rc<Formula>),
+    CheckSat,
+    CheckSatAssuming(Vec<Rc<Formula>>),
+    Push(usize),
+    Pop(usize),
+    Echo(String),
+    Unsupported, // commands such as get-model and get-info, answered with unsupported
+    Exit,
+}
+
+#[derive(Debug)]
+enum SExprKind {
+    Symbol(String), // simple or quoted symbol, without the bars
+    Keyword, // attribute names such as :named, which are ignored
+    Literal(String), // numeral, decimal, hexadecimal or binary
+    String(String),
+    List(Vec<SExpr>),
+}
+
+#[derive(Debug)]
+struct SExpr {
+    offset: usize,
+    kind: SExprKind,
+}
+
+/// A parsed expression, either a term or a formula (an expression of sort Bool)
+#[derive(Clone)]
+enum Expr {
+    Term(Rc<Term>),
+    Formula(Rc<Formula>),
+}
+
+#[derive(Clone)]
+enum Declaration {
+    Function(Rc<FunctionSymbol>),
+    Relation(Rc<RelationSymbol>),
+    Definition(Expr), // a define-fun without arguments, expanded at each use
+}
+
+/// Reads the commands of an SMT-LIB script one at a time, resolving
+/// the sorts and symbols declared in the scopes opened by push.
+///
+/// An application of ite to terms is replaced by a fresh constant `@iteN`,
+/// which is declared right before the assertion and defined by
+/// two implications conjoined to it. In the body of a define-fun,
+/// these implications are asserted right after the definition
+pub struct ScriptReader<'a> {
+    input: &'a str,
+    position: usize,
+    sorts: HashMap<String, Rc<Sort>>,
+    symbols: HashMap<String, Declaration>,
+    scopes: Vec<(Vec<String>, Vec<String>)>, // sorts and symbols declared in each open scope
+    bindings: HashMap<String, Vec<Expr>>, // let bindings in the current assertion
+    num_fresh_constants: usize,
+    pending: VecDeque<Command>,
+    done: bool,
+}
+
+/// Runs the commands of a script against an IncrementalSolver
+pub struct ScriptExecutor {
+    sorts: Vec<Rc<Sort>>,
+    function_symbols: Vec<Rc<FunctionSymbol>>,
+    relation_symbols: Vec<Rc<RelationSymbol>>,
+    language_changed: bool,
+    solver: IncrementalSolver,
+}
+
+impl SmtLibError {
+    fn new(source: &str, offset: usize, message: String) -> SmtLibError {
+        let (line, column) = get_line_column(source, offset);
+        SmtLibError { offset, line, column, message }
+    }
+
+    /// Formats the error as the offending line of the source
+    /// followed by a caret under the position and the message
+    pub fn diagnostic(&self, source: &str) -> String {
+        format_diagnostic(source, self.line, self.column, &self.message)
+    }
+}
+
+impl fmt::Display for SmtLibError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Expr {
+    fn get_sort_name(&self) -> String {
+        match self {
+            Expr::Term(term) => term.get_sort().to_string(),
+            Expr::Formula(_) => "Bool".to_string(),
+        }
+    }
+}
+
+impl<'a> ScriptReader<'a> {
+    pub fn new(input: &'a str) -> ScriptReader<'a> {
+        ScriptReader {
+            input,
+            position: 0,
+            sorts: HashMap::new(),
+            symbols: HashMap::new(),
+            scopes: vec![],
+            bindings: HashMap::new(),
+            num_fresh_constants: 0,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn error<T>(&self, offset: usize, message: impl Into<String>) -> Result<T, SmtLibError> {
+        Err(SmtLibError::new(self.input, offset, message.into()))
+    }
+
+    /// Skips whitespaces and comments
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = &self.input[self.position..];
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+            if !trimmed.starts_with(';') {
+                break;
+            }
+            self.position += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn read_sexpr(&mut self) -> Result<SExpr, SmtLibError> {
+        self.skip_whitespace();
+        let offset = self.position;
+        let rest = &self.input[offset..];
+
+        let kind = match rest.chars().next() {
+            None => return self.error(offset, "unexpected end of input"),
+            Some('(') => {
+                self.position += 1;
+                let mut elements = vec![];
+                loop {
+                    self.skip_whitespace();
+                    if self.input[self.position..].starts_with(')') {
+                        self.position += 1;
+                        break;
+                    }
+                    elements.push(self.read_sexpr()?);
+                }
+                SExprKind::List(elements)
+            },
+            Some(')') => return self.error(offset, "unexpected )"),
+            Some('|') => match rest[1..].find(['|', '\\']) {
+                Some(end) if rest[1 + end..].starts_with('|') => {
+                    self.position += end + 2;
+                    SExprKind::Symbol(rest[1..1 + end].to_string())
+                },
+                Some(end) => return self.error(offset + 1 + end, "quoted symbols cannot contain \\"),
+                None => return self.error(offset, "unterminated quoted symbol"),
+            },
+            Some('"') => {
+                let mut value = String::new();
+                let mut chars = rest.char_indices().skip(1);
+                loop {
+                    match chars.next() {
+                        None => return self.error(offset, "unterminated string literal"),
+                        // "" is an escaped quote
+                        Some((i, '"')) if rest[i + 1..].starts_with('"') => {
+                            value.push('"');
+                            chars.next();
+                        },
+                        Some((i, '"')) => {
+                            self.position += i + 1;
+                            break;
+                        },
+                        Some((_, c)) => value.push(c),
+                    }
+                }
+                SExprKind::String(value)
+            },
+            Some(first) => {
+                let end = rest.find(|c: char| c.is_whitespace() || "()|\";".contains(c)).unwrap_or(rest.len());
+                let token = &rest[..end];
+                self.position += end;
+                if token.starts_with(':') {
+                    SExprKind::Keyword
+                } else if first.is_ascii_digit() || first == '#' {
+                    SExprKind::Literal(token.to_string())
+                } else {
+                    SExprKind::Symbol(token.to_string())
+                }
+            },
+        };
+
+        Ok(SExpr { offset, kind })
+    }
+
+    fn check_arity(&self, sexpr: &SExpr, name: &str, arguments: &[SExpr], min: usize, max: Option<usize>) -> Result<(), SmtLibError> {
+        if arguments.len() < min || max.is_some_and(|max| arguments.len() > max) {
+            let expected = match max {
+                Some(max) if max == min => format!("{}", min),
+                Some(max) => format!("{} to {}", min, max),
+                None => format!("at least {}", min),
+            };
+            return self.error(sexpr.offset, format!("{} expects {} argument(s), found {}", name, expected, arguments.len()));
+        }
+        Ok(())
+    }
+
+    fn symbol<'b>(&self, sexpr: &'b SExpr) -> Result<&'b str, SmtLibError> {
+        match &sexpr.kind {
+            SExprKind::Symbol(name) => Ok(name),
+            _ => self.error(sexpr.offset, "expected a symbol"),
+        }
+    }
+
+    fn numeral(&self, sexpr: &SExpr) -> Result<usize, SmtLibError> {
+        match &sexpr.kind {
+            SExprKind::Literal(literal) => match literal.parse() {
+                Ok(n) => Ok(n),
+                Err(_) => self.error(sexpr.offset, "expected a numeral"),
+            },
+            _ => self.error(sexpr.offset, "expected a numeral"),
+        }
+    }
+
+    /// Resolves a sort, where None stands for Bool
+    fn sort(&self, sexpr: &SExpr) -> Result<Option<Rc<Sort>>, SmtLibError> {
+        match &sexpr.kind {
+            SExprKind::Symbol(name) if name == "Bool" => Ok(None),
+            SExprKind::Symbol(name) => match self.sorts.get(name) {
+                Some(sort) => Ok(Some(sort.clone())),
+                None => self.error(sexpr.offset, format!("unknown sort {}", name)),
+            },
+            SExprKind::List(_) => self.error(sexpr.offset, "parametric sorts are not supported in QF_UF"),
+            _ => self.error(sexpr.offset, "expected a sort"),
+        }
+    }
+
+    fn declare_sort(&mut self, name: &str) -> Rc<Sort> {
+        let sort = Sort::new(name);
+        self.sorts.insert(name.to_string(), sort.clone());
+        if let Some((sorts, _)) = self.scopes.last_mut() {
+            sorts.push(name.to_string());
+        }
+        sort
+    }
+
+    fn declare_symbol(&mut self, name: &str, declaration: Declaration) {
+        self.symbols.insert(name.to_string(), declaration);
+        if let Some((_, symbols)) = self.scopes.last_mut() {
+            symbols.push(name.to_string());
+        }
+    }
+
+    /// Handles declare-fun and declare-const
+    fn declare_function(&mut self, name: &SExpr, input_sorts: &[SExpr], output_sort: &SExpr) -> Result<Command, SmtLibError> {
+        let name_offset = name.offset;
+        let name = self.symbol(name)?;
+        if self.symbols.contains_key(name) {
+            return self.error(name_offset, format!("symbol {} is already declared", name));
+        }
+
+        let input_sorts = input_sorts.iter()
+            .map(|sexpr| match self.sort(sexpr)? {
+                Some(sort) => Ok(sort),
+                None => self.error(sexpr.offset, "arguments of sort Bool are not supported"),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let input_sorts = input_sorts.iter().collect::<Vec<_>>();
+
+        match self.sort(output_sort)? {
+            Some(output_sort) => {
+                let symbol = FunctionSymbol::new(name, &input_sorts, &output_sort);
+                self.declare_symbol(name, Declaration::Function(symbol.clone()));
+                Ok(Command::DeclareFunction(symbol))
+            },
+            None => {
+                let symbol = RelationSymbol::new(name, &input_sorts);
+                self.declare_symbol(name, Declaration::Relation(symbol.clone()));
+                Ok(Command::DeclareRelation(symbol))
+            },
+        }
+    }
+
+    /// Handles define-fun, which only supports definitions without arguments
+    fn define_function(&mut self, name: &SExpr, arguments: &SExpr, sort: &SExpr, body: &SExpr) -> Result<(), SmtLibError> {
+        let name_offset = name.offset;
+        let name = self.symbol(name)?.to_string();
+        if self.symbols.contains_key(&name) {
+            return self.error(name_offset, format!("symbol {} is already declared", name));
+        }
+        if !matches!(&arguments.kind, SExprKind::List(arguments) if arguments.is_empty()) {
+            return self.error(arguments.offset, "define-fun with arguments is not supported");
+        }
+
+        let sort = self.sort(sort)?;
+        self.bindings.clear();
+        let mut side_conditions = vec![];
+        let value = self.expr(body, &mut side_conditions)?;
+        match (&sort, &value) {
+            (Some(sort), Expr::Term(term)) if term.get_sort() == sort => {},
+            (None, Expr::Formula(_)) => {},
+            _ => {
+                let sort_name = sort.map_or("Bool".to_string(), |sort| sort.to_string());
+                return self.error(body.offset, format!("{} is defined with sort {}, found {}", name, sort_name, value.get_sort_name()));
+            },
+        }
+
+        self.declare_symbol(&name, Declaration::Definition(value));
+        if !side_conditions.is_empty() {
+            self.pending.push_back(Command::Assert(Formula::new_conjunction(&side_conditions.iter().collect::<Vec<_>>())));
+        }
+        Ok(())
+    }
+
+    fn formula(&mut self, sexpr: &SExpr, side_conditions: &mut Vec<Rc<Formula>>) -> Result<Rc<Formula>, SmtLibError> {
+        match self.expr(sexpr, side_conditions)? {
+            Expr::Formula(formula) => Ok(formula),
+            Expr::Term(term) => self.error(sexpr.offset, format!("expected a formula, found a term of sort {}", term.get_sort())),
+        }
+    }
+
+    fn formulas(&mut self, sexprs: &[SExpr], side_conditions: &mut Vec<Rc<Formula>>) -> Result<Vec<Rc<Formula>>, SmtLibError> {
+        sexprs.iter().map(|sexpr| self.formula(sexpr, side_conditions)).collect()
+    }
+
+    fn term(&mut self, sexpr: &SExpr, side_conditions: &mut Vec<Rc<Formula>>) -> Result<Rc<Term>, SmtLibError> {
+        match self.expr(sexpr, side_conditions)? {
+            Expr::Term(term) => Ok(term),
+            Expr::Formula(_) => self.error(sexpr.offset, "expected a term, found a formula"),
+        }
+    }
+
+    /// Parses a term or a formula. Definitions needed by the applications
+    /// of ite to terms are added to the side conditions
+    fn expr(&mut self, sexpr: &SExpr, side_conditions: &mut Vec<Rc<Formula>>) -> Result<Expr, SmtLibError> {
+        match &sexpr.kind {
+            SExprKind::Symbol(name) => self.application(sexpr, name, &[], side_conditions),
+            SExprKind::List(elements) if !elements.is_empty() => match &elements[0].kind {
+                SExprKind::Symbol(name) if name == "let" => self.let_expr(sexpr, &elements[1..], side_conditions),
+                // annotations such as :named do not change the meaning
+                SExprKind::Symbol(name) if name == "!" => {
+                    self.check_arity(sexpr, name, &elements[1..], 1, None)?;
+                    self.expr(&elements[1], side_conditions)
+                },
+                SExprKind::Symbol(name) if name == "forall" || name == "exists" =>
+                    self.error(sexpr.offset, "quantifiers are not supported in QF_UF"),
+                SExprKind::Symbol(name) => self.application(sexpr, name, &elements[1..], side_conditions),
+                _ => self.error(elements[0].offset, "expected a function symbol"),
+            },
+            SExprKind::Literal(literal) => self.error(sexpr.offset, format!("literal {} is not supported in QF_UF", literal)),
+            _ => self.error(sexpr.offset, "expected a term or a formula"),
+        }
+    }
+
+    fn let_expr(&mut self, sexpr: &SExpr, arguments: &[SExpr], side_conditions: &mut Vec<Rc<Formula>>) -> Result<Expr, SmtLibError> {
+        self.check_arity(sexpr, "let", arguments, 2, Some(2))?;
+        let bindings = match &arguments[0].kind {
+            SExprKind::List(bindings) if !bindings.is_empty() => bindings,
+            _ => return self.error(arguments[0].offset, "expected a list of bindings"),
+        };
+
+        // the bindings are parallel, so all values are parsed before any name is bound
+        let mut values = vec![];
+        for binding in bindings {
+            match &binding.kind {
+                SExprKind::List(pair) if pair.len() == 2 => {
+                    let name = self.symbol(&pair[0])?.to_string();
+                    values.push((name, self.expr(&pair[1], side_conditions)?));
+                },
+                _ => return self.error(binding.offset, "expected a binding (<symbol> <term>)"),
+            }
+        }
+
+        for (name, value) in &values {
+            self.bindings.entry(name.clone()).or_default().push(value.clone());
+        }
+        let result = self.expr(&arguments[1], side_conditions);
+        for (name, _) in &values {
+            self.bindings.get_mut(name).unwrap().pop();
+        }
+        result
+    }
+
+    /// Builds the equality or the equivalence of two expressions of the same sort
+    fn equality(&self, left: &Expr, right: &Expr, offset: usize) -> Result<Rc<Formula>, SmtLibError> {
+        match (left, right) {
+            (Expr::Formula(left), Expr::Formula(right)) => Ok(Formula::new_equivalence(left, right)),
+            (Expr::Term(left), Expr::Term(right)) if left.get_sort() == right.get_sort() => Ok(Formula::new_equality(left, right)),
+            _ => self.error(offset, format!("comparing sort {} with sort {}", left.get_sort_name(), right.get_sort_name())),
+        }
+    }
+
+    fn application(&mut self, sexpr: &SExpr, name: &str, arguments: &[SExpr], side_conditions: &mut Vec<Rc<Formula>>) -> Result<Expr, SmtLibError> {
+        // let-bound names shadow all other symbols
+        if let Some(value) = self.bindings.get(name).and_then(|values| values.last()) {
+            self.check_arity(sexpr, name, arguments, 0, Some(0))?;
+            return Ok(value.clone());
+        }
+
+        let formula = match name {
+            "true" | "false" => {
+                self.check_arity(sexpr, name, arguments, 0, Some(0))?;
+                if name == "true" { Formula::verum() } else { Formula::falsum() }
+            },
+            "not" => {
+                self.check_arity(sexpr, name, arguments, 1, Some(1))?;
+                Formula::new_negation(&self.formula(&arguments[0], side_conditions)?)
+            },
+            "and" | "or" => {
+                let formulas = self.formulas(arguments, side_conditions)?;
+                let formulas = formulas.iter().collect::<Vec<_>>();
+                if name == "and" { Formula::new_conjunction(&formulas) } else { Formula::new_disjunction(&formulas) }
+            },
+            "=>" => {
+                // implication is right associative
+                self.check_arity(sexpr, name, arguments, 2, None)?;
+                let mut formulas = self.formulas(arguments, side_conditions)?;
+                let last = formulas.pop().unwrap();
+                formulas.iter().rev().fold(last, |right, left| Formula::new_implication(left, &right))
+            },
+            "xor" => {
+                self.check_arity(sexpr, name, arguments, 2, None)?;
+                let formulas = self.formulas(arguments, side_conditions)?;
+                formulas[1..].iter().fold(formulas[0].clone(), |left, right| {
+                    Formula::new_negation(&Formula::new_equivalence(&left, right))
+                })
+            },
+            "=" | "distinct" => {
+                self.check_arity(sexpr, name, arguments, 2, None)?;
+                let exprs = arguments.iter()
+                    .map(|argument| self.expr(argument, side_conditions))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // = is chainable and distinct is pairwise
+                let mut formulas = vec![];
+                for j in 1..exprs.len() {
+                    let range = if name == "=" { j - 1..j } else { 0..j };
+                    for i in range {
+                        let equality = self.equality(&exprs[i], &exprs[j], arguments[j].offset)?;
+                        formulas.push(if name == "=" { equality } else { Formula::new_negation(&equality) });
+                    }
+                }
+
+                if formulas.len() == 1 {
+                    formulas.pop().unwrap()
+                } else {
+                    Formula::new_conjunction(&formulas.iter().collect::<Vec<_>>())
+                }
+            },
+            "ite" => {
+                self.check_arity(sexpr, name, arguments, 3, Some(3))?;
+                let condition = self.formula(&arguments[0], side_conditions)?;
+                let negated_condition = Formula::new_negation(&condition);
+
+                match (self.expr(&arguments[1], side_conditions)?, self.expr(&arguments[2], side_conditions)?) {
+                    (Expr::Formula(then_formula), Expr::Formula(else_formula)) => Formula::new_conjunction(&[
+                        &Formula::new_implication(&condition, &then_formula),
+                        &Formula::new_implication(&negated_condition, &else_formula),
+                    ]),
+                    (Expr::Term(then_term), Expr::Term(else_term)) if then_term.get_sort() == else_term.get_sort() => {
+                        let constant = Term::new_application(&self.fresh_constant(then_term.get_sort()), &[]);
+                        side_conditions.push(Formula::new_implication(&condition, &Formula::new_equality(&constant, &then_term)));
+                        side_conditions.push(Formula::new_implication(&negated_condition, &Formula::new_equality(&constant, &else_term)));
+                        return Ok(Expr::Term(constant));
+                    },
+                    (then_expr, else_expr) => return self.error(
+                        arguments[2].offset,
+                        format!("branches of ite have sorts {} and {}", then_expr.get_sort_name(), else_expr.get_sort_name()),
+                    ),
+                }
+            },
+            _ => match self.symbols.get(name).cloned() {
+                Some(Declaration::Function(symbol)) => {
+                    let arguments = self.arguments(sexpr, name, symbol.get_input_sorts(), arguments, side_conditions)?;
+                    return Ok(Expr::Term(Term::new_application(&symbol, &arguments.iter().collect::<Vec<_>>())));
+                },
+                Some(Declaration::Relation(symbol)) => {
+                    let arguments = self.arguments(sexpr, name, symbol.get_input_sorts(), arguments, side_conditions)?;
+                    Formula::new_relation_application(&symbol, &arguments.iter().collect::<Vec<_>>())
+                },
+                Some(Declaration::Definition(value)) => {
+                    self.check_arity(sexpr, name, arguments, 0, Some(0))?;
+                    return Ok(value);
+                },
+                None => return self.error(sexpr.offset, format!("unknown symbol {}", name)),
+            },
+        };
+
+        Ok(Expr::Formula(formula))
+    }
+
+    /// Parses the arguments of a declared symbol and checks their sorts
+    fn arguments(
+        &mut self,
+        sexpr: &SExpr,
+        name: &str,
+        input_sorts: &[Rc<Sort>],
+        arguments: &[SExpr],
+        side_conditions: &mut Vec<Rc<Formula>>,
+    ) -> Result<Vec<Rc<Term>>, SmtLibError> {
+        self.check_arity(sexpr, name, arguments, input_sorts.len(), Some(input_sorts.len()))?;
+        let mut terms = vec![];
+        for (i, (argument, sort)) in arguments.iter().zip(input_sorts).enumerate() {
+            let term = self.term(argument, side_conditions)?;
+            if term.get_sort() != sort {
+                return self.error(
+                    argument.offset,
+                    format!("argument {} of {} should have sort {}, found {}", i + 1, name, sort, term.get_sort()),
+                );
+            }
+            terms.push(term);
+        }
+        Ok(terms)
+    }
+
+    /// Declares a fresh constant for an application of ite to terms
+    fn fresh_constant(&mut self, sort: &Rc<Sort>) -> Rc<FunctionSymbol> {
+        let name = loop {
+            let name = format!("@ite{}", self.num_fresh_constants);
+            self.num_fresh_constants += 1;
+            if !self.symbols.contains_key(&name) {
+                break name;
+            }
+        };
+        let symbol = FunctionSymbol::new(&name, &[], sort);
+        self.declare_symbol(&name, Declaration::Function(symbol.clone()));
+        self.pending.push_back(Command::DeclareFunction(symbol.clone()));
+        symbol
+    }
+
+    /// Reads a command, or None for commands that do not affect the solver
+    fn command(&mut self, sexpr: &SExpr) -> Result<Option<Command>, SmtLibError> {
+        let elements = match &sexpr.kind {
+            SExprKind::List(elements) if !elements.is_empty() => elements,
+            _ => return self.error(sexpr.offset, "expected a command"),
+        };
+        let name = self.symbol(&elements[0])?;
+        let arguments = &elements[1..];
+
+        let command = match name {
+            "set-info" | "set-option" => return Ok(None),
+            "set-logic" => {
+                self.check_arity(sexpr, name, arguments, 1, Some(1))?;
+                Command::SetLogic(self.symbol(&arguments[0])?.to_string())
+            },
+            "declare-sort" => {
+                self.check_arity(sexpr, name, arguments, 1, Some(2))?;
+                let sort_name = self.symbol(&arguments[0])?;
+                if sort_name == "Bool" || self.sorts.contains_key(sort_name) {
+                    return self.error(arguments[0].offset, format!("sort {} is already declared", sort_name));
+                }
+                if arguments.len() == 2 && self.numeral(&arguments[1])? != 0 {
+                    return self.error(arguments[1].offset, "parametric sorts are not supported in QF_UF");
+                }
+                Command::DeclareSort(self.declare_sort(sort_name))
+            },
+            "declare-fun" => {
+                self.check_arity(sexpr, name, arguments, 3, Some(3))?;
+                match &arguments[1].kind {
+                    SExprKind::List(input_sorts) => self.declare_function(&arguments[0], input_sorts, &arguments[2])?,
+                    _ => return self.error(arguments[1].offset, "expected a list of sorts"),
+                }
+            },
+            "declare-const" => {
+                self.check_arity(sexpr, name, arguments, 2, Some(2))?;
+                self.declare_function(&arguments[0], &[], &arguments[1])?
+            },
+            "define-fun" => {
+                self.check_arity(sexpr, name, arguments, 4, Some(4))?;
+                self.define_function(&arguments[0], &arguments[1], &arguments[2], &arguments[3])?;
+                return Ok(None);
+            },
+            "assert" => {
+                self.check_arity(sexpr, name, arguments, 1, Some(1))?;
+                self.bindings.clear();
+                let mut side_conditions = vec![];
+                let formula = self.formula(&arguments[0], &mut side_conditions)?;
+                if side_conditions.is_empty() {
+                    Command::Assert(formula)
+                } else {
+                    side_conditions.insert(0, formula);
+                    Command::Assert(Formula::new_conjunction(&side_conditions.iter().collect::<Vec<_>>()))
+                }
+            },
+            "check-sat" => {
+                self.check_arity(sexpr, name, arguments, 0, Some(0))?;
+                Command::CheckSat
+            },
+            "check-sat-assuming" => {
+                self.check_arity(sexpr, name, arguments, 1, Some(1))?;
+                let literals = match &arguments[0].kind {
+                    SExprKind::List(literals) => literals,
+                    _ => return self.error(arguments[0].offset, "expected a list of literals"),
+                };
+
+                // the definitions of the applications of ite are assumed together with each literal
+                self.bindings.clear();
+                let mut assumptions = vec![];
+                for literal in literals {
+                    let mut side_conditions = vec![];
+                    let formula = self.formula(literal, &mut side_conditions)?;
+                    side_conditions.insert(0, formula);
+                    assumptions.push(Formula::new_conjunction(&side_conditions.iter().collect::<Vec<_>>()));
+                }
+                Command::CheckSatAssuming(assumptions)
+            },
+            "push" | "pop" => {
+                self.check_arity(sexpr, name, arguments, 0, Some(1))?;
+                let n = match arguments.first() {
+                    Some(argument) => self.numeral(argument)?,
+                    None => 1,
+                };
+
+                if name == "push" {
+                    self.scopes.extend((0..n).map(|_| (vec![], vec![])));
+                    Command::Push(n)
+                } else {
+                    if n > self.scopes.len() {
+                        return self.error(sexpr.offset, format!("cannot pop {} scope(s), only {} pushed", n, self.scopes.len()));
+                    }
+                    for (sorts, symbols) in self.scopes.split_off(self.scopes.len() - n) {
+                        for sort in sorts {
+                            self.sorts.remove(&sort);
+                        }
+                        for symbol in symbols {
+                            self.symbols.remove(&symbol);
+                        }
+                    }
+                    Command::Pop(n)
+                }
+            },
+            "echo" => {
+                self.check_arity(sexpr, name, arguments, 1, Some(1))?;
+                match &arguments[0].kind {
+                    SExprKind::String(string) => Command::Echo(string.clone()),
+                    _ => return self.error(arguments[0].offset, "expected a string literal"),
+                }
+            },
+            "get-model" | "get-value" | "get-info" | "get-option" | "get-assertions"
+                | "get-assignment" | "get-proof" | "get-unsat-core" => Command::Unsupported,
+            "exit" => {
+                self.check_arity(sexpr, name, arguments, 0, Some(0))?;
+                Command::Exit
+            },
+            _ => return self.error(elements[0].offset, format!("unsupported command {}", name)),
+        };
+
+        Ok(Some(command))
+    }
+}
+
+impl Iterator for ScriptReader<'_> {
+    type Item = Result<Command, SmtLibError>;
+
+    /// Reads the next command, stopping after an error or exit
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(command) = self.pending.pop_front() {
+                return Some(Ok(command));
+            }
+
+            self.skip_whitespace();
+            if self.done || self.position == self.input.len() {
+                return None;
+            }
+
+            let result = self.read_sexpr().and_then(|sexpr| self.command(&sexpr));
+            match result {
+                Ok(None) => {},
+                Ok(Some(command)) => {
+                    self.done = matches!(command, Command::Exit);
+                    self.pending.push_back(command);
+                },
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                },
+            }
+        }
+    }
+}
+
+impl Default for ScriptExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptExecutor {
+    pub fn new() -> ScriptExecutor {
+        ScriptExecutor {
+            sorts: vec![],
+            function_symbols: vec![],
+            relation_symbols: vec![],
+            language_changed: false,
+            solver: IncrementalSolver::new(&Language::new(&[], &[], &[])),
+        }
+    }
+
+    /// Get the language of all sorts and symbols declared so far, including popped ones
+    pub fn get_language(&self) -> Rc<Language> {
+        Language::new(
+            &self.sorts.iter().collect::<Vec<_>>(),
+            &self.function_symbols.iter().collect::<Vec<_>>(),
+            &self.relation_symbols.iter().collect::<Vec<_>>(),
+        )
+    }
+
+    fn update_language(&mut self) {
+        if self.language_changed {
+            self.solver.extend_language(&self.get_language());
+            self.language_changed = false;
+        }
+    }
+
+    /// Executes a command and returns the response to print, if any
+    pub fn execute(&mut self, command: &Command) -> Option<String> {
+        match command {
+            Command::SetLogic(_) | Command::Exit => {},
+            Command::DeclareSort(sort) => {
+                self.sorts.push(sort.clone());
+                self.language_changed = true;
+            },
+            Command::DeclareFunction(symbol) => {
+                self.function_symbols.push(symbol.clone());
+                self.language_changed = true;
+            },
+            Command::DeclareRelation(symbol) => {
+                self.relation_symbols.push(symbol.clone());
+                self.language_changed = true;
+            },
+            Command::Assert(formula) => {
+                self.update_language();
+                if let Err(errors) = self.solver.assert(formula) {
+                    let messages = errors.iter().map(|error| error.to_string()).collect::<Vec<_>>();
+                    return Some(format!("(error \"{}\")", messages.join("; ").replace('"', "\"\"")));
+                }
+            },
+            Command::CheckSat | Command::CheckSatAssuming(_) => {
+                self.update_language();
+                let result = match command {
+                    Command::CheckSatAssuming(assumptions) => self.solver.check_assuming(assumptions),
+                    _ => self.solver.check(),
+                };
+                return Some(match result {
+                    SatResult::Sat(_) => "sat",
+                    SatResult::Unsat => "unsat",
+                    _ => "unknown",
+                }.to_string());
+            },
+            Command::Push(n) => (0..*n).for_each(|_| self.solver.push()),
+            Command::Pop(n) => self.solver.pop(*n),
+            Command::Echo(string) => return Some(format!("\"{}\"", string.replace('"', "\"\""))),
+            Command::Unsupported => return Some("unsupported".to_string()),
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(script: &str) -> Result<Vec<String>, SmtLibError> {
+        let mut executor = ScriptExecutor::new();
+        let mut responses = vec![];
+        for command in ScriptReader::new(script) {
+            responses.extend(executor.execute(&command?));
+        }
+        Ok(responses)
+    }
+
+    #[test]
+    fn scripts() {
+        let script = r#"
+            (set-info :status unsat)
+            (set-logic QF_UF)
+            (declare-sort U 0)
+            (declare-fun f (U) U)
+            (declare-fun |a b| () U)
+            (declare-const b U)
+            (declare-fun p (U) Bool)
+            (assert (= (f (f (f |a b|))) |a b|))
+            (assert (p b))
+            (check-sat)
+            (push 1)
+            (assert (and (= (f (f (f (f (f |a b|))))) |a b|) (not (= (f |a b|) |a b|))))
+            (check-sat)
+            (pop 1)
+            (push)
+            (declare-const c U)
+            (assert (=> (= b c) (p c) false))
+            (assert (= b c))
+            (check-sat)
+            (pop)
+            ; c is no longer declared, so it can be declared again
+            (declare-const c U)
+            (check-sat)
+            (echo "done ""here""")
+            (exit)
+            (check-sat)
+        "#;
+        assert_eq!(run(script).unwrap(), ["sat", "unsat", "unsat", "sat", "\"done \"\"here\"\"\""]);
+
+        let script = r#"
+            (declare-sort U 0)
+            (declare-fun g (U U) U)
+            (declare-const a U)
+            (declare-const b U)
+            (declare-const q Bool)
+            (assert (! (let ((x (g a b)) (y (ite q a b))) (distinct x y (g y a))) :named n))
+            (assert (xor q (= a b) true))
+            (check-sat)
+            (assert (= (g a b) (ite (= a b) a (g b b))))
+            (assert (= a b))
+            (assert (= (g a a) a))
+            (check-sat)
+        "#;
+        assert_eq!(run(script).unwrap(), ["sat", "unsat"]);
+    }
+
+    #[test]
+    fn benchmark_script() {
+        // shaped like the QF_UF benchmarks of SMT-LIB
+        let script = r#"
+            (set-info :smt-lib-version 2.6)
+            (set-logic QF_UF)
+            (set-info :source |
+            Generated by hand, in the layout of the benchmark library.
+            |)
+            (set-info :category "crafted")
+            (set-info :status unsat)
+            (declare-sort U 0)
+            (declare-fun f (U) U)
+            (declare-fun a () U)
+            (declare-fun b () U)
+            (declare-fun p (U) Bool)
+            (define-fun fa () U (f a))
+            (define-fun same () Bool (= fa (f b)))
+            (define-fun pick () U (ite (p a) a b))
+            (assert (p fa))
+            (assert (= (f pick) fa))
+            (check-sat)
+            (get-info :reason-unknown)
+            (get-model)
+            (get-value (a fa))
+            (check-sat-assuming ((= a b) (not same)))
+            (check-sat-assuming ((p a) (not (p (f b))) (= pick a)))
+            (push 1)
+            (define-fun fb () U (f b))
+            (assert (not (p fb)))
+            (check-sat)
+            (pop 1)
+            (assert (= a b))
+            (check-sat)
+            (exit)
+        "#;
+        assert_eq!(run(script).unwrap(), ["sat", "unsupported", "unsupported", "unsupported", "unsat", "sat", "sat", "sat"]);
+
+        let error1 = run("(declare-sort U 0)\n(declare-const a U)\n(define-fun b () Bool a)").unwrap_err();
+        assert_eq!((error1.line, error1.column), (3, 23));
+        assert_eq!(error1.message, "b is defined with sort Bool, found U");
+
+        let error2 = run("(declare-sort U 0)\n(define-fun g ((x U)) U x)").unwrap_err();
+        assert_eq!(error2.message, "define-fun with arguments is not supported");
+    }
+
+    #[test]
+    fn printed_scripts_read_back() {
+        let sort_a = Sort::new("A");
+        let sort_b = Sort::new("my sort");
+        let function_f = FunctionSymbol::new("f", &[&sort_a], &sort_a);
+        let function_g = FunctionSymbol::new("assert", &[&sort_a, &sort_a], &sort_b);
+        let constant_a = FunctionSymbol::new("a'", &[], &sort_a);
+        let constant_b = FunctionSymbol::new("1b", &[], &sort_b);
+        let relation_p = RelationSymbol::new("P", &[&sort_a, &sort_b]);
+        let relation_q = RelationSymbol::new("q", &[]);
+        let constant_x0 = FunctionSymbol::new("x0", &[], &sort_a);
+        let language = Language::new(
+            &[&sort_a, &sort_b],
+            &[&function_f, &function_g, &constant_a, &constant_b, &constant_x0],
+            &[&relation_p, &relation_q],
+        );
+
+        let a = Term::new_application(&constant_a, &[]);
+        let b = Term::new_application(&constant_b, &[]);
+        let fa = Term::new_application(&function_f, &[&a]);
+        let gfa = Term::new_application(&function_g, &[&fa, &a]);
+        let q = Formula::new_relation_application(&relation_q, &[]);
+        let formulas = [
+            Formula::new_equivalence(&q, &Formula::new_relation_application(&relation_p, &[&fa, &gfa])),
+            Formula::new_implication(&Formula::new_negation(&q), &Formula::new_conjunction(&[&Formula::new_equality(&gfa, &b)])),
+            Formula::new_disjunction(&[&Formula::verum(), &Formula::falsum(), &Formula::new_equality(&fa, &a)]),
+            Formula::new_equality(&Term::new_application(&constant_x0, &[]), &fa),
+        ];
+
+        let script = language.to_smtlib_script(&formulas).unwrap();
+        assert_eq!(script, [
+            "(set-logic QF_UF)",
+            "(declare-sort A 0)",
+            "(declare-sort |my sort| 0)",
+            "(declare-fun f (A) A)",
+            "(declare-fun |assert| (A A) |my sort|)",
+            "(declare-fun |a'| () A)",
+            "(declare-fun |1b| () |my sort|)",
+            "(declare-fun x0 () A)",
+            "(declare-fun P (A |my sort|) Bool)",
+            "(declare-fun q () Bool)",
+            "(assert (= q (P (f |a'|) (|assert| (f |a'|) |a'|))))",
+            "(assert (=> (not q) (and (= (|assert| (f |a'|) |a'|) |1b|))))",
+            "(assert (or true false (= (f |a'|) |a'|)))",
+            "(assert (= x0 (f |a'|)))",
+            "(check-sat)",
+            "",
+        ].join("\n"));
+
+        // the script reads back to the same language and formulas
+        let mut executor = ScriptExecutor::new();
+        let mut read_formulas = vec![];
+        for command in ScriptReader::new(&script) {
+            let command = command.unwrap();
+            if let Command::Assert(formula) = &command {
+                read_formulas.push(formula.clone());
+            }
+            executor.execute(&command);
+        }
+        let read_language = executor.get_language();
+        assert!(read_language.iter_sorts().eq(language.iter_sorts()));
+        assert!(read_language.iter_function_symbols().eq(language.iter_function_symbols()));
+        assert!(read_language.iter_relation_symbols().eq(language.iter_relation_symbols()));
+        assert_eq!(read_formulas, formulas);
+
+        // quantifiers and free variables would not read back
+        let x0_a = Term::new_variable(0, &sort_a);
+        let open = Formula::new_equality(&x0_a, &a);
+        assert!(matches!(
+            language.to_smtlib_script(&[formulas[0].clone(), open.clone()]),
+            Err(SmtLibPrintError::FreeVariable { formula, .. }) if formula == open,
+        ));
+        let x0 = Rc::new(Variable { index: 0, sort: sort_a.clone() });
+        let quantified = Rc::new(Formula::UniversalQuantification(x0, open));
+        assert!(matches!(language.to_smtlib_script(&[quantified]), Err(SmtLibPrintError::Quantifier { .. })));
+
+        let sort_c = Sort::new("c|d");
+        let language = Language::new(&[&sort_a, &sort_c], &[], &[]);
+        assert!(matches!(language.to_smtlib_script(&[]), Err(SmtLibPrintError::Name { name }) if name == "c|d"));
+    }
+
+    #[test]
+    fn script_errors() {
+        let error = |script: &str| run(script).unwrap_err();
+
+        let error1 = error("(declare-sort U 0)\n(declare-fun f (U) U)\n(assert (= (f f) f))");
+        assert_eq!((error1.line, error1.column), (3, 15));
+        assert_eq!(error1.message, "f expects 1 argument(s), found 0");
+
+        let error2 = error("(declare-sort U 0)\n(declare-const a U)\n(assert (= a true))");
+        assert_eq!((error2.line, error2.column), (3, 14));
+        assert_eq!(error2.message, "comparing sort U with sort Bool");
+
+        let error3 = error("(push 1)\n(declare-const a Bool)\n(pop 1)\n(assert a)");
+        assert_eq!((error3.line, error3.column), (4, 9));
+        assert_eq!(error3.message, "unknown symbol a");
+
+        let error4 = error("(declare-sort U 0) (pop 2)");
+        assert_eq!(error4.offset, 19);
+        assert_eq!(error4.message, "cannot pop 2 scope(s), only 0 pushed");
+
+        let error5 = error("(declare-sort U 0)\n(assert (forall ((x U)) (= x x)))");
+        assert_eq!(error5.message, "quantifiers are not supported in QF_UF");
+        assert_eq!(error5.diagnostic("(declare-sort U 0)\n(assert (forall ((x U)) (= x x)))"),
+            "(assert (forall ((x U)) (= x x)))\n        ^ quantifiers are not supported in QF_UF");
+
+        let error6 = error("(assert (and true)");
+        assert_eq!(error6.message, "unexpected end of input");
+    }
+}
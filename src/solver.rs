@@ -1,91 +1,508 @@
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::panic;
 use std::rc::Rc;
 use std::fmt;
 
+use crate::ackermann::*;
 use crate::congruence::*;
 use crate::fol::*;
+use crate::model::*;
+use crate::sat::*;
 
 type Literal = (bool, Rc<Formula>);
 type Clause = Vec<Literal>;
 type ClauseList = Vec<Clause>;
 
+/// Atom of a definitional CNF, either an atom of the input formula
+/// or a fresh Boolean variable defined to be equivalent to a subformula
+#[derive(Clone, Debug)]
+pub enum DefinitionalAtom {
+    Atom(Rc<Formula>),
+    Definition(usize),
+}
+
+pub type DefinitionalLiteral = (bool, DefinitionalAtom);
+pub type DefinitionalClause = Vec<DefinitionalLiteral>;
+
+/// A CNF formula equisatisfiable with the input, produced by introducing
+/// a definition for each compound subformula (Plaisted-Greenbaum encoding).
+/// A definition is only constrained in the polarities its subformula occurs in,
+/// and shared subformulas (the same Rc) are defined once, so the number of
+/// clauses is linear in the size of the input
+pub struct DefinitionalCNF {
+    pub clauses: Vec<DefinitionalClause>,
+    definitions: Vec<Rc<Formula>>, // subformula of each definition
+    polarities: Vec<(bool, bool)>, // whether each definition has been encoded positively/negatively
+    definition_index: HashMap<*const Formula, usize>,
+}
+
+impl Default for DefinitionalCNF {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DefinitionalCNF {
+    pub fn new() -> DefinitionalCNF {
+        DefinitionalCNF {
+            clauses: vec![],
+            definitions: vec![],
+            polarities: vec![],
+            definition_index: HashMap::new(),
+        }
+    }
+
+    /// Add clauses asserting the given formula. Top-level
+    /// conjunctions are split without introducing definitions
+    pub fn add_formula(&mut self, formula: &Rc<Formula>) {
+        match formula.borrow() {
+            Formula::Conjunction(conjuncts) => {
+                for conjunct in conjuncts {
+                    self.add_formula(conjunct);
+                }
+            },
+            Formula::Disjunction(disjuncts) if disjuncts.len() == 1 => self.add_formula(&disjuncts[0]),
+            _ => {
+                let literal = self.encode(formula, true);
+                self.clauses.push(vec![literal]);
+            },
+        }
+    }
+
+    /// Get a literal that implies the given formula without asserting it,
+    /// adding the clauses for the definitions the literal depends on
+    pub fn define_formula(&mut self, formula: &Rc<Formula>) -> DefinitionalLiteral {
+        self.encode(formula, true)
+    }
+
+    /// Get the number of definitions introduced so far
+    pub fn get_num_definitions(&self) -> usize {
+        self.definitions.len()
+    }
+
+    /// Get the subformula that a definition stands for
+    pub fn get_definition(&self, index: usize) -> &Rc<Formula> {
+        &self.definitions[index]
+    }
+
+    fn negate(literal: &DefinitionalLiteral) -> DefinitionalLiteral {
+        (!literal.0, literal.1.clone())
+    }
+
+    /// Get a literal equivalent to the formula, and add the clauses for the
+    /// direction of its definition needed when it occurs in the given polarity
+    fn encode(&mut self, formula: &Rc<Formula>, positive: bool) -> DefinitionalLiteral {
+        match formula.borrow() {
+            Formula::Negation(formula) => DefinitionalCNF::negate(&self.encode(formula, !positive)),
+
+            // unary conjunctions and disjunctions do not need a definition
+            Formula::Conjunction(formulas) | Formula::Disjunction(formulas) if formulas.len() == 1 =>
+                self.encode(&formulas[0], positive),
+
+            Formula::Implication(..) | Formula::Equivalence(..) |
+            Formula::Conjunction(..) | Formula::Disjunction(..) => self.define(formula, positive),
+
+            // will not do deeper if hit atomic formula or quantifiers
+            _ => (false, DefinitionalAtom::Atom(formula.clone())),
+        }
+    }
+
+    fn define(&mut self, formula: &Rc<Formula>, positive: bool) -> DefinitionalLiteral {
+        let index = match self.definition_index.get(&Rc::as_ptr(formula)) {
+            Some(index) => *index,
+            None => {
+                self.definitions.push(formula.clone());
+                self.polarities.push((false, false));
+                self.definition_index.insert(Rc::as_ptr(formula), self.definitions.len() - 1);
+                self.definitions.len() - 1
+            },
+        };
+
+        let definition = (false, DefinitionalAtom::Definition(index));
+        let not_definition = (true, DefinitionalAtom::Definition(index));
+
+        let polarities = &mut self.polarities[index];
+        let encoded = if positive { &mut polarities.0 } else { &mut polarities.1 };
+        if *encoded {
+            return definition;
+        }
+        *encoded = true;
+
+        match formula.borrow() {
+            Formula::Conjunction(conjuncts) => {
+                let literals = conjuncts.iter().map(|conjunct| self.encode(conjunct, positive)).collect::<Vec<_>>();
+                if positive {
+                    // d -> l for each conjunct l
+                    for literal in literals {
+                        self.clauses.push(vec![not_definition.clone(), literal]);
+                    }
+                } else {
+                    // l1 /\ ... /\ ln -> d
+                    let mut clause = vec![definition.clone()];
+                    clause.extend(literals.iter().map(DefinitionalCNF::negate));
+                    self.clauses.push(clause);
+                }
+            },
+
+            Formula::Disjunction(disjuncts) => {
+                let literals = disjuncts.iter().map(|disjunct| self.encode(disjunct, positive)).collect::<Vec<_>>();
+                if positive {
+                    // d -> l1 \/ ... \/ ln
+                    let mut clause = vec![not_definition.clone()];
+                    clause.extend(literals);
+                    self.clauses.push(clause);
+                } else {
+                    // l -> d for each disjunct l
+                    for literal in literals {
+                        self.clauses.push(vec![definition.clone(), DefinitionalCNF::negate(&literal)]);
+                    }
+                }
+            },
+
+            Formula::Implication(left, right) => {
+                let left = self.encode(left, !positive);
+                let right = self.encode(right, positive);
+                if positive {
+                    self.clauses.push(vec![not_definition.clone(), DefinitionalCNF::negate(&left), right]);
+                } else {
+                    self.clauses.push(vec![definition.clone(), left]);
+                    self.clauses.push(vec![definition.clone(), DefinitionalCNF::negate(&right)]);
+                }
+            },
+
+            Formula::Equivalence(left, right) => {
+                // both sides occur in both polarities
+                self.encode(left, !positive);
+                self.encode(right, !positive);
+                let left = self.encode(left, positive);
+                let right = self.encode(right, positive);
+                if positive {
+                    self.clauses.push(vec![not_definition.clone(), DefinitionalCNF::negate(&left), right.clone()]);
+                    self.clauses.push(vec![not_definition.clone(), left, DefinitionalCNF::negate(&right)]);
+                } else {
+                    self.clauses.push(vec![definition.clone(), left.clone(), right.clone()]);
+                    self.clauses.push(vec![definition.clone(), DefinitionalCNF::negate(&left), DefinitionalCNF::negate(&right)]);
+                }
+            },
+
+            _ => unreachable!("only compound formulas are defined"),
+        }
+
+        definition
+    }
+}
+
 pub enum SatResult {
-    Sat,
+    Sat(Model),
     Unsat,
     Unknown,
+    IllSorted(Vec<SortError>), // the input does not sort check against the language
+}
+
+/// Write "ill-sorted" followed by one error per line
+fn write_sort_errors(f: &mut fmt::Formatter<'_>, errors: &[SortError]) -> fmt::Result {
+    write!(f, "ill-sorted")?;
+    for error in errors {
+        write!(f, "\n{}", error)?;
+    }
+    Ok(())
 }
 
 impl fmt::Display for SatResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            SatResult::Sat => write!(f, "sat"),
+            SatResult::Sat(_) => write!(f, "sat"),
             SatResult::Unsat => write!(f, "unsat"),
             SatResult::Unknown => write!(f, "unknown"),
+            SatResult::IllSorted(errors) => write_sort_errors(f, errors),
+        }
+    }
+}
+
+/// Result of an unsat core query
+pub enum UnsatCoreResult {
+    Sat(Model),
+    Unsat(Vec<Rc<Formula>>), // an unsatisfiable subset of the top-level conjuncts
+    Unknown, // some conjunct has quantifiers
+    IllSorted(Vec<SortError>),
+}
+
+impl fmt::Display for UnsatCoreResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnsatCoreResult::Sat(_) => write!(f, "sat"),
+            UnsatCoreResult::Unsat(core) => {
+                write!(f, "unsat")?;
+                for conjunct in core {
+                    write!(f, "\n{}", conjunct)?;
+                }
+                Ok(())
+            },
+            UnsatCoreResult::Unknown => write!(f, "unknown"),
+            UnsatCoreResult::IllSorted(errors) => write_sort_errors(f, errors),
         }
     }
 }
 
+/// Result of a validity or entailment query
+pub enum ValidityResult {
+    Valid(Vec<Rc<Formula>>), // a minimal set of premises implying the conclusion
+    Invalid(Model), // a counter-model, with one element per equivalence class
+    Unknown, // some premise or the conclusion has quantifiers
+    IllSorted(Vec<SortError>),
+}
+
+impl fmt::Display for ValidityResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidityResult::Valid(premises) => {
+                write!(f, "valid")?;
+                for premise in premises {
+                    write!(f, "\n{}", premise)?;
+                }
+                Ok(())
+            },
+            ValidityResult::Invalid(_) => write!(f, "invalid"),
+            ValidityResult::Unknown => write!(f, "unknown"),
+            ValidityResult::IllSorted(errors) => write_sort_errors(f, errors),
+        }
+    }
+}
+
+/// Symbol of a node in the congruence graph
+#[derive(Clone, PartialEq, Eq)]
+enum Symbol {
+    Function(Rc<FunctionSymbol>),
+    Relation(Rc<RelationSymbol>),
+    /// A free variable, which is treated as a constant
+    Variable(Rc<Variable>),
+    /// Symbol of the distinguished node that an application
+    /// of a relation symbol is equal to iff it holds
+    True,
+}
+
 /// A solver for quantifier-free theory of equality and uninterpreted functions
 pub struct QFEUFSolver {
     pub congruence_graph: CongruenceGraph,
-    symbol_table: Vec<Rc<FunctionSymbol>>,
+    language: Rc<Language>,
+    symbol_table: Vec<Symbol>,
+    true_node: NodeIndex,
+    atoms: HashMap<Var, (NodeIndex, NodeIndex)>, // equality atom of each propositional variable
+    atom_vars: HashMap<(NodeIndex, NodeIndex), Var>, // inverse of atoms, with the nodes ordered
 }
 
 impl QFEUFSolver {
     pub fn new(language: &Rc<Language>) -> QFEUFSolver {
-        // TODO: handle relation
+        let mut symbol_table = vec![Symbol::True];
+        symbol_table.extend(language.iter_function_symbols().map(|symbol| Symbol::Function(symbol.clone())));
+        symbol_table.extend(language.iter_relation_symbols().map(|symbol| Symbol::Relation(symbol.clone())));
+
+        // the true node is added before any push, so it is never removed
+        let mut congruence_graph = CongruenceGraph::new();
+        let true_node = congruence_graph.add_node(0, &vec![]);
+
         QFEUFSolver {
-            congruence_graph: CongruenceGraph::new(),
-            symbol_table: language.iter_function_symbols().map(|x| x.clone()).collect(),
+            congruence_graph,
+            language: language.clone(),
+            symbol_table,
+            true_node,
+            atoms: HashMap::new(),
+            atom_vars: HashMap::new(),
         }
     }
 
     /// Add a symbol if it does not exist
     pub fn add_symbol(&mut self, symbol: &Rc<FunctionSymbol>) -> SymbolIndex {
-        if let Some(index) = self.symbol_table.iter().position(|x| x == symbol) {
+        self.add_symbol_entry(Symbol::Function(symbol.clone()))
+    }
+
+    /// Add a relation symbol if it does not exist
+    pub fn add_relation_symbol(&mut self, symbol: &Rc<RelationSymbol>) -> SymbolIndex {
+        self.add_symbol_entry(Symbol::Relation(symbol.clone()))
+    }
+
+    fn add_symbol_entry(&mut self, symbol: Symbol) -> SymbolIndex {
+        if let Some(index) = self.symbol_table.iter().position(|x| x == &symbol) {
             return index;
         }
-        self.symbol_table.push(symbol.clone());
-        return self.symbol_table.len() - 1;
+        self.symbol_table.push(symbol);
+        self.symbol_table.len() - 1
+    }
+
+    fn find_symbol_id(&self, symbol: &Rc<FunctionSymbol>) -> Option<SymbolIndex> {
+        self.symbol_table.iter().position(|other| matches!(other, Symbol::Function(other) if other == symbol))
+    }
+
+    fn find_relation_symbol_id(&self, symbol: &Rc<RelationSymbol>) -> Option<SymbolIndex> {
+        self.symbol_table.iter().position(|other| matches!(other, Symbol::Relation(other) if other == symbol))
     }
 
     pub fn get_symbol_id(&self, symbol: &Rc<FunctionSymbol>) -> SymbolIndex {
-        for (i, other) in self.symbol_table.iter().enumerate() {
-            if other == symbol {
-                return i;
-            }
+        match self.find_symbol_id(symbol) {
+            Some(index) => index,
+            None => panic!("symbol {} not found", symbol),
+        }
+    }
+
+    pub fn get_relation_symbol_id(&self, symbol: &Rc<RelationSymbol>) -> SymbolIndex {
+        match self.find_relation_symbol_id(symbol) {
+            Some(index) => index,
+            None => panic!("relation symbol {} not found", symbol),
         }
-        panic!("symbol {} not found", symbol);
     }
 
-    /// Add a term to the congruence graph and return the node index
+    /// Get the node that holding applications of relation symbols are equal to
+    pub fn get_true_node(&self) -> NodeIndex {
+        self.true_node
+    }
+
+    /// Add a term to the congruence graph and return the node index.
+    /// Free variables are rigid: each of them is a constant of its sort
     pub fn add_term(&mut self, term: &Rc<Term>) -> NodeIndex {
         match term.borrow() {
-            Term::Variable(_) => panic!("variable not supported"),
+            Term::Variable(variable) => {
+                let symbol_id = self.add_symbol_entry(Symbol::Variable(variable.clone()));
+                self.congruence_graph.add_node(symbol_id, &vec![])
+            },
             Term::Application(symbol, arguments) => {
                 let symbol_id = self.get_symbol_id(symbol);
                 if arguments.is_empty() {
                     // constant
-                    return self.congruence_graph.add_node(symbol_id, &vec![]);
+                    self.congruence_graph.add_node(symbol_id, &vec![])
                 } else {
                     // application
                     let mut children = vec![];
                     for argument in arguments {
                         children.push(self.add_term(argument));
                     }
-                    return self.congruence_graph.add_node(symbol_id, &children);
+                    self.congruence_graph.add_node(symbol_id, &children)
                 }
             }
         }
     }
 
+    /// Find the node of a term without adding it to the congruence graph
+    pub fn find_term(&self, term: &Rc<Term>) -> Option<NodeIndex> {
+        match term.borrow() {
+            Term::Variable(variable) => {
+                let symbol_id = self.symbol_table.iter().position(|other| matches!(other, Symbol::Variable(other) if other == variable))?;
+                self.congruence_graph.find_node(symbol_id, &[])
+            },
+            Term::Application(symbol, arguments) => {
+                let symbol_id = self.find_symbol_id(symbol)?;
+                let children = arguments.iter().map(|argument| self.find_term(argument)).collect::<Option<Vec<_>>>()?;
+                self.congruence_graph.find_node(symbol_id, &children)
+            }
+        }
+    }
+
+    /// Add an application of a relation symbol to the congruence graph and return
+    /// the node index. The application holds iff the node is equal to the true node
+    pub fn add_relation_application(&mut self, symbol: &Rc<RelationSymbol>, arguments: &[Rc<Term>]) -> NodeIndex {
+        let symbol_id = self.get_relation_symbol_id(symbol);
+        let children = arguments.iter().map(|argument| self.add_term(argument)).collect();
+        self.congruence_graph.add_node(symbol_id, &children)
+    }
+
+    /// Add the terms of an atomic formula to the congruence graph and return
+    /// the pair of nodes whose equality is equivalent to the formula
+    fn add_atom(&mut self, atom: &Rc<Formula>) -> (NodeIndex, NodeIndex) {
+        match atom.borrow() {
+            Formula::Equality(left, right) => (self.add_term(left), self.add_term(right)),
+            Formula::RelationApplication(symbol, arguments) =>
+                (self.add_relation_application(symbol, arguments), self.true_node),
+            _ => panic!("not an atomic formula: {}", atom),
+        }
+    }
+
     pub fn check_equality(&self, node1: NodeIndex, node2: NodeIndex) -> bool {
-        return self.congruence_graph.get_congruent_class(node1) == self.congruence_graph.get_congruent_class(node2);
+        self.congruence_graph.get_congruent_class(node1) == self.congruence_graph.get_congruent_class(node2)
+    }
+
+    pub fn add_equality(&mut self, node1: NodeIndex, node2: NodeIndex) -> Result<(), Conflict> {
+        self.congruence_graph.merge_congruence_classes(node1, node2)
+    }
+
+    pub fn add_disequality(&mut self, node1: NodeIndex, node2: NodeIndex) -> Result<(), Conflict> {
+        self.congruence_graph.add_disequality(node1, node2)
     }
 
-    pub fn add_equality(&mut self, node1: NodeIndex, node2: NodeIndex) {
-        self.congruence_graph.merge_congruence_classes(node1, node2);
+    /// Return a minimal set of added equalities that implies node1 = node2
+    pub fn explain_equality(&self, node1: NodeIndex, node2: NodeIndex) -> Option<Vec<(NodeIndex, NodeIndex)>> {
+        self.congruence_graph.explain(node1, node2)
+    }
+
+    /// Get the term that a node was added for
+    pub fn get_term(&self, node: NodeIndex) -> Rc<Term> {
+        let graph = &self.congruence_graph;
+        match &self.symbol_table[graph.get_symbol(node)] {
+            Symbol::Function(symbol) => {
+                let arguments = graph.get_children(node).iter().map(|child| self.get_term(*child)).collect::<Vec<_>>();
+                Term::new_application(symbol, &arguments.iter().collect::<Vec<_>>())
+            },
+            Symbol::Variable(variable) => Rc::new(Term::Variable(variable.clone())),
+            _ => panic!("node {} is not a term", node),
+        }
+    }
+
+    /// Get the atomic formula that is equivalent to the equality of two nodes
+    pub fn get_atom(&self, node1: NodeIndex, node2: NodeIndex) -> Rc<Formula> {
+        let graph = &self.congruence_graph;
+        let (node, other) = if node1 == self.true_node { (node2, node1) } else { (node1, node2) };
+
+        match &self.symbol_table[graph.get_symbol(node)] {
+            Symbol::Relation(symbol) if other == self.true_node => {
+                let arguments = graph.get_children(node).iter().map(|child| self.get_term(*child)).collect::<Vec<_>>();
+                Formula::new_relation_application(symbol, &arguments.iter().collect::<Vec<_>>())
+            },
+            _ => Formula::new_equality(&self.get_term(node1), &self.get_term(node2)),
+        }
+    }
+
+    /// Return a minimal set of added atoms that implies node1 = node2
+    pub fn explain_equality_atoms(&self, node1: NodeIndex, node2: NodeIndex) -> Option<Vec<Rc<Formula>>> {
+        let explanation = self.explain_equality(node1, node2)?;
+        Some(explanation.iter().map(|(left, right)| self.get_atom(*left, *right)).collect())
+    }
+
+    /// Build a model of the equalities and disequalities added so far,
+    /// with one domain element per congruence class
+    pub fn get_model(&self) -> Model {
+        let graph = &self.congruence_graph;
+        let mut model = Model::new(&self.language);
+        let mut class_elements = HashMap::new();
+
+        // nodes of relation applications are never equal to terms,
+        // so their classes are not elements of the domain
+        for node in 0..graph.get_num_nodes() {
+            let sort = match &self.symbol_table[graph.get_symbol(node)] {
+                Symbol::Function(symbol) => symbol.get_output_sort(),
+                Symbol::Variable(variable) => &variable.sort,
+                _ => continue,
+            };
+            class_elements.entry(graph.get_congruent_class(node))
+                .or_insert_with(|| model.add_element(sort));
+        }
+
+        for node in 0..graph.get_num_nodes() {
+            let arguments = graph.get_children(node).iter()
+                .map(|child| class_elements[&graph.get_congruent_class(*child)])
+                .collect();
+
+            match &self.symbol_table[graph.get_symbol(node)] {
+                Symbol::Function(symbol) =>
+                    model.set_function_value(symbol, arguments, class_elements[&graph.get_congruent_class(node)]),
+                Symbol::Relation(symbol) =>
+                    model.set_relation_value(symbol, arguments, self.check_equality(node, self.true_node)),
+                Symbol::Variable(variable) =>
+                    model.set_variable_value(variable, class_elements[&graph.get_congruent_class(node)]),
+                Symbol::True => {},
+            }
+        }
+
+        model.complete();
+        model
     }
 
     pub fn flip_literals(clauses: &mut ClauseList) {
@@ -123,7 +540,7 @@ impl QFEUFSolver {
                 )),
                 
             Formula::Conjunction(conjuncts) =>
-                conjuncts.iter().map(|conjunct| QFEUFSolver::to_cnf(conjunct)).collect::<Vec<_>>().concat(),
+                conjuncts.iter().map(QFEUFSolver::to_cnf).collect::<Vec<_>>().concat(),
 
             Formula::Disjunction(disjuncts) => {
                 if disjuncts.is_empty() {
@@ -148,6 +565,14 @@ impl QFEUFSolver {
         }
     }
 
+    /// Convert a formula to an equisatisfiable CNF formula of linear size,
+    /// using fresh definitions for compound subformulas instead of distributing
+    pub fn to_definitional_cnf(formula: &Rc<Formula>) -> DefinitionalCNF {
+        let mut cnf = DefinitionalCNF::new();
+        cnf.add_formula(formula);
+        cnf
+    }
+
     /// Convert a formula to an equivalent DNF formula
     pub fn to_dnf(formula: &Rc<Formula>) -> ClauseList {
         match formula.borrow() {
@@ -189,7 +614,7 @@ impl QFEUFSolver {
             },
 
             Formula::Disjunction(disjuncts) =>
-                disjuncts.iter().map(|disjunct| QFEUFSolver::to_dnf(disjunct)).collect::<Vec<_>>().concat(),
+                disjuncts.iter().map(QFEUFSolver::to_dnf).collect::<Vec<_>>().concat(),
 
             // will not do deeper if hit atomic formula or quantifiers
             _ => vec![vec![(false, formula.clone())]],
@@ -198,49 +623,774 @@ impl QFEUFSolver {
 
     /// Check if a clause (conjunction) is satisfiable
     pub fn clause_sat(language: &Rc<Language>, clause: &Clause) -> SatResult {
-        let mut solver = QFEUFSolver::new(language);
+        QFEUFSolver::new(language).check_clause(clause)
+    }
+
+    /// Check if a clause (conjunction) is satisfiable together with
+    /// the equalities already added to the congruence graph
+    pub fn check_clause(&mut self, clause: &Clause) -> SatResult {
         let mut equalities = vec![];
         let mut negated_equalities = vec![];
 
         // add all terms
         for (negated, formula) in clause {
-            if let Formula::Equality(left, right) = formula.borrow() {
-                let node1 = solver.add_term(left);
-                let node2 = solver.add_term(right);
-                if *negated {
-                    negated_equalities.push((node1, node2));
-                } else {
-                    equalities.push((node1, node2));
-                }
+            let (node1, node2) = self.add_atom(formula);
+            if *negated {
+                negated_equalities.push((node1, node2));
             } else {
-                panic!("Relation not supported");
+                equalities.push((node1, node2));
             }
         }
 
-        for (node1, node2) in equalities {
-            solver.add_equality(node1, node2);
+        // add the disequalities first so that
+        // merging can stop at the first conflict
+        for (node1, node2) in negated_equalities {
+            if self.add_disequality(node1, node2).is_err() {
+                return SatResult::Unsat;
+            }
         }
 
-        for (node1, node2) in negated_equalities {
-            if solver.check_equality(node1, node2) {
+        for (node1, node2) in equalities {
+            if self.add_equality(node1, node2).is_err() {
                 return SatResult::Unsat;
             }
         }
 
-        SatResult::Sat
+        SatResult::Sat(self.get_model())
     }
 
-    /// Check if the given QF_EUF formula is satisfiable
+    /// Map an atom to a propositional variable of the search,
+    /// creating a new variable if the atom has not been seen
+    fn abstract_atom(search: &mut CDCLSolver<QFEUFSolver>, atom: &Rc<Formula>) -> Var {
+        let (node1, node2) = search.theory.add_atom(atom);
+        let key = (node1.min(node2), node1.max(node2));
+
+        if let Some(var) = search.theory.atom_vars.get(&key) {
+            return *var;
+        }
+
+        let var = search.new_var();
+        search.theory.atoms.insert(var, key);
+        search.theory.atom_vars.insert(key, var);
+        var
+    }
+
+    /// Get the propositional variable of the equality between two nodes
+    fn get_atom_var(&self, node1: NodeIndex, node2: NodeIndex) -> Var {
+        self.atom_vars[&(node1.min(node2), node1.max(node2))]
+    }
+
+    /// Turn a conflict into a clause over the atoms: the violated disequality
+    /// cannot hold together with a minimal set of the equalities implying it
+    fn get_conflict_clause(&self, conflict: Conflict) -> Vec<Lit> {
+        let (node1, node2) = conflict.disequality;
+        let explanation = self.congruence_graph.explain(node1, node2).unwrap();
+
+        let mut clause = vec![Lit::new(self.get_atom_var(node1, node2), false)];
+        for (left, right) in explanation {
+            clause.push(Lit::new(self.get_atom_var(left, right), true));
+        }
+        clause
+    }
+
+    /// Sort check the formulas against the language, collecting the errors of all of them
+    fn check_sorts(language: &Rc<Language>, formulas: &[Rc<Formula>]) -> Result<(), Vec<SortError>> {
+        let errors = formulas.iter().flat_map(|formula| language.check_formula(formula)).collect::<Vec<_>>();
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Check if the given QF_EUF formula is satisfiable, using a CDCL search over
+    /// the Boolean abstraction of its definitional CNF with the congruence graph as the theory
     pub fn sat(language: &Rc<Language>, formula: &Rc<Formula>) -> SatResult {
-        // TODO: instead of DNF, use a faster way to search for sat assignments
+        let mut solver = IncrementalSolver::new(language);
+        match solver.assert(formula) {
+            Ok(()) => solver.check(),
+            Err(errors) => SatResult::IllSorted(errors),
+        }
+    }
+
+    /// Add the clauses of a definitional CNF to the search, with fresh variables for
+    /// the definitions. If a guard is given, the clauses only apply when it is true.
+    /// Returns the variable of each definition
+    fn add_definitional_cnf(search: &mut CDCLSolver<QFEUFSolver>, cnf: &DefinitionalCNF, guard: Option<Var>) -> Vec<Var> {
+        // new atoms must be added to the theory at level 0, or they would be undone on backtracking
+        search.reset();
+
+        // definitions are propositional variables unknown to the theory
+        let definition_vars = (0..cnf.get_num_definitions()).map(|_| search.new_var()).collect::<Vec<_>>();
+
+        for clause in &cnf.clauses {
+            let mut clause = clause.iter()
+                .map(|literal| QFEUFSolver::abstract_literal(search, &definition_vars, literal))
+                .collect::<Vec<_>>();
+            if let Some(guard) = guard {
+                clause.push(Lit::new(guard, true));
+            }
+            search.add_clause(&clause);
+        }
+
+        definition_vars
+    }
+
+    /// Map a literal of a definitional CNF to a literal of the search
+    fn abstract_literal(search: &mut CDCLSolver<QFEUFSolver>, definition_vars: &[Var], literal: &DefinitionalLiteral) -> Lit {
+        match literal {
+            (negated, DefinitionalAtom::Atom(atom)) => Lit::new(QFEUFSolver::abstract_atom(search, atom), *negated),
+            (negated, DefinitionalAtom::Definition(index)) => Lit::new(definition_vars[*index], *negated),
+        }
+    }
+
+    /// Collect the top-level conjuncts of a formula, flattening nested conjunctions
+    fn collect_conjuncts(formula: &Rc<Formula>, conjuncts: &mut Vec<Rc<Formula>>) {
+        match formula.borrow() {
+            Formula::Conjunction(formulas) => {
+                for formula in formulas {
+                    QFEUFSolver::collect_conjuncts(formula, conjuncts);
+                }
+            },
+            Formula::Disjunction(formulas) if formulas.len() == 1 =>
+                QFEUFSolver::collect_conjuncts(&formulas[0], conjuncts),
+            _ => conjuncts.push(formula.clone()),
+        }
+    }
+
+    /// Check if the given QF_EUF formula is satisfiable, and if not, find a subset
+    /// of its top-level conjuncts that is already unsatisfiable. Each conjunct is
+    /// guarded by a selector variable, and the core is read off the selectors
+    /// assumed by the failed search. If minimize is set, the core is shrunk by
+    /// deleting one conjunct at a time until removing any of them makes it satisfiable
+    pub fn unsat_core(language: &Rc<Language>, formula: &Rc<Formula>, minimize: bool) -> UnsatCoreResult {
+        let mut conjuncts = vec![];
+        QFEUFSolver::collect_conjuncts(formula, &mut conjuncts);
+        QFEUFSolver::unsat_core_of_conjuncts(language, &conjuncts, minimize)
+    }
+
+    /// Same as unsat_core, but with the conjuncts given as a list
+    pub fn unsat_core_of_conjuncts(language: &Rc<Language>, conjuncts: &[Rc<Formula>], minimize: bool) -> UnsatCoreResult {
+        if let Err(errors) = QFEUFSolver::check_sorts(language, conjuncts) {
+            return UnsatCoreResult::IllSorted(errors);
+        }
+        if conjuncts.iter().any(|conjunct| conjunct.has_quantifiers()) {
+            return UnsatCoreResult::Unknown;
+        }
+
+        let mut search = CDCLSolver::new(QFEUFSolver::new(language));
+        let mut selectors = vec![];
+        for conjunct in conjuncts {
+            let selector = search.new_var();
+            QFEUFSolver::add_definitional_cnf(&mut search, &QFEUFSolver::to_definitional_cnf(conjunct), Some(selector));
+            selectors.push(Lit::new(selector, false));
+        }
+
+        // indices of the conjuncts in the current core
+        let mut core = match search.solve(&selectors) {
+            SearchResult::Sat => return UnsatCoreResult::Sat(search.theory.get_model()),
+            SearchResult::Unsat => QFEUFSolver::get_failed_conjuncts(&search, &selectors, &(0..conjuncts.len()).collect::<Vec<_>>()),
+        };
+
+        if minimize {
+            // a conjunct found necessary stays necessary in every
+            // subset of the current core, so it is never checked again
+            let mut i = 0;
+            while i < core.len() {
+                let candidate = core.iter().enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, index)| *index)
+                    .collect::<Vec<_>>();
+                let assumptions = candidate.iter().map(|index| selectors[*index]).collect::<Vec<_>>();
+
+                match search.solve(&assumptions) {
+                    SearchResult::Sat => i += 1,
+                    SearchResult::Unsat => {
+                        // the conjuncts before i are necessary, so they stay in place
+                        core = QFEUFSolver::get_failed_conjuncts(&search, &selectors, &candidate);
+                    },
+                }
+            }
+        }
+
+        UnsatCoreResult::Unsat(core.iter().map(|index| conjuncts[*index].clone()).collect())
+    }
+
+    /// Check if the given QF_EUF formula is valid, i.e. its negation is unsatisfiable
+    pub fn valid(language: &Rc<Language>, formula: &Rc<Formula>) -> ValidityResult {
+        QFEUFSolver::entails(language, &[], formula)
+    }
+
+    /// Check if the premises entail the conclusion, i.e. the premises together with
+    /// the negation of the conclusion are unsatisfiable. If so, the result contains
+    /// a minimal subset of the premises that still entails the conclusion, and
+    /// otherwise a model of the premises in which the conclusion is false
+    pub fn entails(language: &Rc<Language>, premises: &[Rc<Formula>], conclusion: &Rc<Formula>) -> ValidityResult {
+        let negated_conclusion = Formula::new_negation(conclusion);
+        let mut conjuncts = premises.to_vec();
+        conjuncts.push(negated_conclusion.clone());
+
+        match QFEUFSolver::unsat_core_of_conjuncts(language, &conjuncts, true) {
+            UnsatCoreResult::Sat(model) => ValidityResult::Invalid(model),
+            UnsatCoreResult::Unknown => ValidityResult::Unknown,
+            UnsatCoreResult::IllSorted(errors) => ValidityResult::IllSorted(errors),
+            UnsatCoreResult::Unsat(core) => ValidityResult::Valid(
+                core.into_iter().filter(|conjunct| !Rc::ptr_eq(conjunct, &negated_conclusion)).collect(),
+            ),
+        }
+    }
+
+    /// Get the conjuncts among the candidates whose selectors failed in the last search
+    fn get_failed_conjuncts(search: &CDCLSolver<QFEUFSolver>, selectors: &[Lit], candidates: &[usize]) -> Vec<usize> {
+        let failed = search.get_failed_assumptions();
+        candidates.iter().copied().filter(|index| failed.contains(&selectors[*index])).collect()
+    }
+
+    /// Check if the given QF_EUF formula is satisfiable by Ackermann's reduction,
+    /// which replaces function applications by fresh constants constrained to be
+    /// functionally consistent. The reduced formula only has constants and is decided
+    /// by case splits checked with a union-find, without congruence closure or the
+    /// CDCL search, so this is independent of QFEUFSolver::sat and is useful for cross-checking
+    pub fn sat_ackermann(language: &Rc<Language>, formula: &Rc<Formula>) -> SatResult {
+        if let Err(errors) = QFEUFSolver::check_sorts(language, std::slice::from_ref(formula)) {
+            return SatResult::IllSorted(errors);
+        }
+
+        let reduction = match AckermannReduction::new(language, formula) {
+            Some(reduction) => reduction,
+            None => return SatResult::Unknown,
+        };
+        match reduction.solve() {
+            Some(model) => SatResult::Sat(reduction.translate_model(language, &model)),
+            None => SatResult::Unsat,
+        }
+    }
+
+    /// Check if the given QF_EUF formula is satisfiable by converting it to DNF
+    /// and checking each disjunct. This takes exponential time on most inputs
+    /// and is kept as a reference implementation for testing
+    pub fn sat_dnf(language: &Rc<Language>, formula: &Rc<Formula>) -> SatResult {
+        if let Err(errors) = QFEUFSolver::check_sorts(language, std::slice::from_ref(formula)) {
+            return SatResult::IllSorted(errors);
+        }
+        if formula.has_quantifiers() {
+            return SatResult::Unknown;
+        }
+
         let dnf = QFEUFSolver::to_dnf(formula);
 
+        // share one congruence graph across the disjuncts
+        // and roll it back after checking each of them
+        let mut solver = QFEUFSolver::new(language);
+
         for clause in dnf {
-            if let SatResult::Sat = QFEUFSolver::clause_sat(language, &clause) {
-                return SatResult::Sat;
+            solver.congruence_graph.push();
+            let result = solver.check_clause(&clause);
+            solver.congruence_graph.pop(1);
+
+            if let SatResult::Sat(_) = result {
+                return result;
             }
         }
 
         SatResult::Unsat
     }
 }
+
+/// A stateful QF_EUF solver for sequences of related queries. Assertions are kept
+/// in a stack of scopes, where each scope opened by push has a selector variable
+/// guarding the clauses asserted in it. A check assumes the selectors of the open
+/// scopes, and pop disables the selectors of the closed scopes for good, so the
+/// atoms, the congruence graph and the learned clauses are reused across queries
+pub struct IncrementalSolver {
+    search: CDCLSolver<QFEUFSolver>,
+    scopes: Vec<Var>, // selector of each open scope
+    assumptions: Vec<(Lit, Rc<Formula>)>, // assumptions of the last check
+    assumption_lits: HashMap<Rc<Formula>, Lit>, // literal defined for each assumption so far
+    quantified: Vec<usize>, // number of open scopes when each quantified formula was asserted
+}
+
+impl IncrementalSolver {
+    pub fn new(language: &Rc<Language>) -> IncrementalSolver {
+        IncrementalSolver {
+            search: CDCLSolver::new(QFEUFSolver::new(language)),
+            scopes: vec![],
+            assumptions: vec![],
+            assumption_lits: HashMap::new(),
+            quantified: vec![],
+        }
+    }
+
+    /// Add a formula to the current scope. The formula is
+    /// not added if it does not sort check against the language.
+    /// A quantified formula is only recorded, and the checks
+    /// answer unknown until the scope asserting it is closed
+    pub fn assert(&mut self, formula: &Rc<Formula>) -> Result<(), Vec<SortError>> {
+        QFEUFSolver::check_sorts(&self.search.theory.language, std::slice::from_ref(formula))?;
+        if formula.has_quantifiers() {
+            self.quantified.push(self.scopes.len());
+            return Ok(());
+        }
+        let cnf = QFEUFSolver::to_definitional_cnf(formula);
+        QFEUFSolver::add_definitional_cnf(&mut self.search, &cnf, self.scopes.last().copied());
+        Ok(())
+    }
+
+    /// Replace the language by a larger one, which declares the sorts
+    /// and symbols of the formulas asserted from now on
+    pub fn extend_language(&mut self, language: &Rc<Language>) {
+        let theory = &mut self.search.theory;
+        for symbol in language.iter_function_symbols() {
+            theory.add_symbol(symbol);
+        }
+        for symbol in language.iter_relation_symbols() {
+            theory.add_relation_symbol(symbol);
+        }
+        theory.language = language.clone();
+    }
+
+    /// Open a new scope
+    pub fn push(&mut self) {
+        let selector = self.search.new_var();
+        self.scopes.push(selector);
+    }
+
+    /// Close the n most recent scopes, removing the formulas asserted in them
+    pub fn pop(&mut self, n: usize) {
+        assert!(n <= self.scopes.len(), "cannot pop {} scopes, only {} pushed", n, self.scopes.len());
+        for selector in self.scopes.split_off(self.scopes.len() - n) {
+            self.search.add_clause(&[Lit::new(selector, true)]);
+        }
+        let num_scopes = self.scopes.len();
+        self.quantified.retain(|scopes| *scopes <= num_scopes);
+    }
+
+    /// Get the number of open scopes
+    pub fn get_num_scopes(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Check if the formulas asserted in the open scopes are satisfiable
+    pub fn check(&mut self) -> SatResult {
+        self.check_assuming(&[])
+    }
+
+    /// Check if the formulas asserted in the open scopes are satisfiable
+    /// together with the given formulas, which are not asserted
+    pub fn check_assuming(&mut self, assumptions: &[Rc<Formula>]) -> SatResult {
+        self.assumptions.clear();
+        if let Err(errors) = QFEUFSolver::check_sorts(&self.search.theory.language, assumptions) {
+            return SatResult::IllSorted(errors);
+        }
+        if !self.quantified.is_empty() || assumptions.iter().any(|assumption| assumption.has_quantifiers()) {
+            return SatResult::Unknown;
+        }
+
+        // the definitions of an assumption are added once and kept, since they
+        // do not constrain the formulas in the scopes unless the literal is assumed
+        for assumption in assumptions {
+            let lit = match self.assumption_lits.get(assumption) {
+                Some(lit) => *lit,
+                None => {
+                    let mut cnf = DefinitionalCNF::new();
+                    let literal = cnf.define_formula(assumption);
+                    let definition_vars = QFEUFSolver::add_definitional_cnf(&mut self.search, &cnf, None);
+                    let lit = QFEUFSolver::abstract_literal(&mut self.search, &definition_vars, &literal);
+                    self.assumption_lits.insert(assumption.clone(), lit);
+                    lit
+                },
+            };
+            self.assumptions.push((lit, assumption.clone()));
+        }
+
+        let mut lits = self.scopes.iter().map(|selector| Lit::new(*selector, false)).collect::<Vec<_>>();
+        lits.extend(self.assumptions.iter().map(|(lit, _)| *lit));
+
+        match self.search.solve(&lits) {
+            SearchResult::Sat => SatResult::Sat(self.search.theory.get_model()),
+            SearchResult::Unsat => SatResult::Unsat,
+        }
+    }
+
+    /// After an unsat check, get the assumptions of the check that
+    /// are inconsistent with the formulas in the open scopes
+    pub fn get_failed_assumptions(&self) -> Vec<Rc<Formula>> {
+        let failed = self.search.get_failed_assumptions();
+        self.assumptions.iter()
+            .filter(|(lit, _)| failed.contains(lit))
+            .map(|(_, assumption)| assumption.clone())
+            .collect()
+    }
+}
+
+impl Theory for QFEUFSolver {
+    fn assert_literal(&mut self, lit: Lit) -> Result<(), Vec<Lit>> {
+        let (node1, node2) = match self.atoms.get(&lit.get_var()) {
+            Some(atom) => *atom,
+            None => return Ok(()),
+        };
+
+        let result = if lit.is_negated() {
+            self.add_disequality(node1, node2)
+        } else {
+            self.add_equality(node1, node2)
+        };
+
+        result.map_err(|conflict| self.get_conflict_clause(conflict))
+    }
+
+    fn push(&mut self) {
+        self.congruence_graph.push();
+    }
+
+    fn pop(&mut self, n: usize) {
+        self.congruence_graph.pop(n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::UnsortedParser;
+
+    #[test]
+    fn conflict_clauses_are_minimal() {
+        // the proof forest explains b = c by all four equalities,
+        // but f(a) = f(f(a)) is not needed to derive f(d) = f(f(f(a)))
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+        let formula = parser.parse_formula(r"f(a) = f(f(a)) /\ d = f(f(a)) /\ c = f(d) /\ f(f(f(a))) = b /\ b != c").unwrap();
+        let conjuncts = match formula.borrow() {
+            Formula::Disjunction(disjuncts) => match disjuncts[0].borrow() {
+                Formula::Conjunction(conjuncts) => conjuncts.clone(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        let mut search = CDCLSolver::new(QFEUFSolver::new(&parser.get_language()));
+        let mut lits = vec![];
+        for conjunct in &conjuncts {
+            match conjunct.borrow() {
+                Formula::Negation(atom) => lits.push(Lit::new(QFEUFSolver::abstract_atom(&mut search, atom), true)),
+                _ => lits.push(Lit::new(QFEUFSolver::abstract_atom(&mut search, conjunct), false)),
+            }
+        }
+
+        for lit in &lits[..4] {
+            assert!(search.theory.assert_literal(*lit).is_ok());
+        }
+        let conflict = search.theory.assert_literal(lits[4]).unwrap_err();
+        assert_eq!(conflict.len(), 4);
+        assert!(!conflict.contains(&Lit::new(lits[0].get_var(), true)));
+    }
+
+    fn parse(input: &str) -> (Rc<Language>, Rc<Formula>) {
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+        let formula = parser.parse_formula(input).unwrap_or_else(|error| panic!("failed to parse:\n{}", error.diagnostic(input)));
+        (parser.get_language(), formula)
+    }
+
+    fn is_sat(input: &str) -> bool {
+        let (language, formula) = parse(input);
+        let result = QFEUFSolver::sat(&language, &formula);
+        let expected = QFEUFSolver::sat_dnf(&language, &formula);
+        assert_eq!(matches!(result, SatResult::Sat(_)), matches!(expected, SatResult::Sat(_)), "{}", input);
+
+        match QFEUFSolver::sat_ackermann(&language, &formula) {
+            SatResult::Sat(model) => {
+                assert!(matches!(result, SatResult::Sat(_)), "{}", input);
+                assert_eq!(model.eval_formula(&formula, &Assignment::new()), Some(true), "{}", input);
+            },
+            _ => assert!(matches!(result, SatResult::Unsat), "{}", input),
+        }
+        matches!(result, SatResult::Sat(_))
+    }
+
+    #[test]
+    fn definitional_cnf_is_linear() {
+        // (a1 = b1 /\ c1 = d1) \/ ... \/ (an = bn /\ cn = dn) has 2^n clauses
+        // when the disjunction is distributed over the conjunctions
+        let n = 16;
+        let disjuncts = (0..n).map(|i| format!("(a{} = b{} /\\ c{} = d{})", i, i, i, i)).collect::<Vec<_>>();
+        let (_, formula) = parse(&disjuncts.join(" \\/ "));
+
+        let cnf = QFEUFSolver::to_definitional_cnf(&formula);
+        assert_eq!(cnf.get_num_definitions(), n + 1);
+        assert_eq!(cnf.clauses.len(), 2 * n + 2);
+
+        // the first definition is the whole disjunction
+        assert!(Rc::ptr_eq(cnf.get_definition(0), &formula));
+    }
+
+    #[test]
+    fn definitional_cnf_agrees_with_dnf() {
+        assert!(!is_sat(r"!(a = b /\ b = c -> f(a) = f(c))"));
+        assert!(!is_sat(r"f(f(f(a))) = a /\ f(f(f(f(f(a))))) = a /\ f(a) != f(f(a))"));
+        assert!(is_sat(r"f(a) = f(f(f(a))) /\ f(a) != f(f(a))"));
+        assert!(is_sat(r"(a = b \/ a = c) /\ !(a = b /\ a = c) /\ f(b) != f(c)"));
+        assert!(!is_sat(r"(a = b \/ a = c) /\ b = c /\ !(f(a) = f(b))"));
+        assert!(!is_sat(r"!(a = b -> (b = c -> a = c))"));
+        assert!(is_sat(r"!((a = b -> b = c) -> a = c)"));
+    }
+
+    #[test]
+    fn relation_applications() {
+        let sort_a = Sort::new("A");
+        let constant_a = FunctionSymbol::new("a", &[], &sort_a);
+        let constant_b = FunctionSymbol::new("b", &[], &sort_a);
+        let function_f = FunctionSymbol::new("f", &[&sort_a], &sort_a);
+        let relation_p = RelationSymbol::new("P", &[&sort_a]);
+        let relation_q = RelationSymbol::new("Q", &[]);
+        let language = Language::new(&[&sort_a], &[&constant_a, &constant_b, &function_f], &[&relation_p, &relation_q]);
+
+        let a = Term::new_application(&constant_a, &[]);
+        let b = Term::new_application(&constant_b, &[]);
+        let fa = Term::new_application(&function_f, &[&a]);
+        let pa = Formula::new_relation_application(&relation_p, &[&a]);
+        let pb = Formula::new_relation_application(&relation_p, &[&b]);
+        let pfa = Formula::new_relation_application(&relation_p, &[&fa]);
+        let q = Formula::new_relation_application(&relation_q, &[]);
+
+        // P(a) /\ a = b /\ ¬P(b) is unsat by congruence
+        let formula = Formula::new_conjunction(&[&pa, &Formula::new_equality(&a, &b), &Formula::new_negation(&pb)]);
+        assert!(matches!(QFEUFSolver::sat(&language, &formula), SatResult::Unsat));
+        assert!(matches!(QFEUFSolver::sat_dnf(&language, &formula), SatResult::Unsat));
+        assert!(matches!(QFEUFSolver::sat_ackermann(&language, &formula), SatResult::Unsat));
+
+        // (P(a) -> Q) /\ (Q -> ¬P(f(a))) /\ P(a) /\ (a = f(a) \/ P(b)) is sat only with a != f(a)
+        let formula = Formula::new_conjunction(&[
+            &Formula::new_implication(&pa, &q),
+            &Formula::new_implication(&q, &Formula::new_negation(&pfa)),
+            &pa,
+            &Formula::new_disjunction(&[&Formula::new_equality(&a, &fa), &pb]),
+        ]);
+        match QFEUFSolver::sat(&language, &formula) {
+            SatResult::Sat(model) => {
+                assert_eq!(model.eval_formula(&formula, &Assignment::new()), Some(true));
+                assert_eq!(model.eval_formula(&Formula::new_equality(&a, &fa), &Assignment::new()), Some(false));
+            },
+            _ => panic!("expected sat"),
+        }
+        match QFEUFSolver::sat_ackermann(&language, &formula) {
+            SatResult::Sat(model) => assert_eq!(model.eval_formula(&formula, &Assignment::new()), Some(true)),
+            _ => panic!("expected sat"),
+        }
+    }
+
+    #[test]
+    fn free_variables_are_constants() {
+        let sort_a = Sort::new("A");
+        let sort_b = Sort::new("B");
+        let constant_a = FunctionSymbol::new("a", &[], &sort_a);
+        let function_f = FunctionSymbol::new("f", &[&sort_a], &sort_a);
+        let language = Language::new(&[&sort_a, &sort_b], &[&constant_a, &function_f], &[]);
+
+        let a = Term::new_application(&constant_a, &[]);
+        let x = Term::new_variable(0, &sort_a);
+        let y = Term::new_variable(1, &sort_a);
+        let fx = Term::new_application(&function_f, &[&x]);
+        let fa = Term::new_application(&function_f, &[&a]);
+
+        // x = a /\ f(x) != f(a) is unsat
+        let formula = Formula::new_conjunction(&[
+            &Formula::new_equality(&x, &a),
+            &Formula::new_negation(&Formula::new_equality(&fx, &fa)),
+        ]);
+        assert!(matches!(QFEUFSolver::sat(&language, &formula), SatResult::Unsat));
+
+        // x = f(y) /\ y != a is sat, and the model assigns both variables
+        let formula = Formula::new_conjunction(&[
+            &Formula::new_equality(&x, &Term::new_application(&function_f, &[&y])),
+            &Formula::new_negation(&Formula::new_equality(&y, &a)),
+        ]);
+        match QFEUFSolver::sat(&language, &formula) {
+            SatResult::Sat(model) => {
+                assert_eq!(model.eval_formula(&formula, &Assignment::new()), Some(true));
+                assert!(model.to_string().contains("x0:A = e"));
+                assert!(model.to_string().contains("x1:A = e"));
+            },
+            _ => panic!("expected sat"),
+        }
+
+        // x0:B = a is rejected before deciding
+        let formula = Formula::new_equality(&Term::new_variable(0, &sort_b), &a);
+        assert!(matches!(QFEUFSolver::sat(&language, &formula), SatResult::IllSorted(errors) if errors.len() == 1));
+
+        // variables with the same index but different sorts are different nodes
+        let mut solver = QFEUFSolver::new(&language);
+        let x_a = solver.add_term(&x);
+        let x_b = solver.add_term(&Term::new_variable(0, &sort_b));
+        assert_ne!(x_a, x_b);
+        assert_eq!(solver.find_term(&x), Some(x_a));
+
+        // explanations refer to the variables by name
+        let node_a = solver.add_term(&a);
+        let node_fx = solver.add_term(&fx);
+        let node_fa = solver.add_term(&fa);
+        solver.add_equality(x_a, node_a).unwrap();
+        let explanation = solver.explain_equality_atoms(node_fx, node_fa).unwrap();
+        assert_eq!(explanation.len(), 1);
+        assert_eq!(explanation[0].to_string(), "x0:A = a()");
+    }
+
+    #[test]
+    fn incremental_push_pop() {
+        let (language, formula) = parse(r"a = b /\ f(a) != f(b) /\ b = c /\ c != a /\ (a = d \/ g(a) = g(d))");
+        let conjuncts = match formula.borrow() {
+            Formula::Disjunction(disjuncts) => match disjuncts[0].borrow() {
+                Formula::Conjunction(conjuncts) => conjuncts.clone(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        let mut solver = IncrementalSolver::new(&language);
+        solver.assert(&conjuncts[0]).unwrap();
+        assert!(matches!(solver.check(), SatResult::Sat(_)));
+
+        solver.push();
+        solver.assert(&conjuncts[1]).unwrap();
+        assert!(matches!(solver.check(), SatResult::Unsat));
+        solver.pop(1);
+        assert!(matches!(solver.check(), SatResult::Sat(_)));
+
+        solver.push();
+        solver.assert(&conjuncts[4]).unwrap();
+        solver.push();
+        solver.assert(&conjuncts[2]).unwrap();
+        assert_eq!(solver.get_num_scopes(), 2);
+        assert!(matches!(solver.check_assuming(&[conjuncts[3].clone(), conjuncts[4].clone()]), SatResult::Unsat));
+        assert_eq!(solver.get_failed_assumptions().len(), 1);
+        assert!(Rc::ptr_eq(&solver.get_failed_assumptions()[0], &conjuncts[3]));
+
+        // assumptions are not asserted
+        match solver.check() {
+            SatResult::Sat(model) => {
+                for conjunct in [0, 2, 4] {
+                    assert_eq!(model.eval_formula(&conjuncts[conjunct], &Assignment::new()), Some(true));
+                }
+            },
+            _ => panic!("expected sat"),
+        }
+
+        solver.pop(2);
+        assert!(matches!(solver.check_assuming(&[conjuncts[3].clone()]), SatResult::Sat(_)));
+        assert!(matches!(solver.check_assuming(&[conjuncts[1].clone()]), SatResult::Unsat));
+    }
+
+    #[test]
+    fn repeated_assumptions_do_not_grow_the_search() {
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+        let background = parser.parse_formula(r"f(a) = b /\ (g(b) = c \/ g(b) = d)").unwrap();
+        let queries = [r"c != d /\ g(f(a)) != c", r"a = b -> f(b) = b", r"g(b) != c /\ g(b) != d"];
+        for query in queries {
+            parser.parse_formula(query).unwrap();
+        }
+
+        let mut solver = IncrementalSolver::new(&parser.get_language());
+        solver.assert(&background).unwrap();
+
+        let mut sizes = vec![];
+        for _ in 0..1000 {
+            for query in queries {
+                // a new but equal formula in each round
+                let assumption = parser.parse_formula(query).unwrap();
+                solver.check_assuming(&[assumption]);
+            }
+            sizes.push((solver.search.get_num_vars(), solver.search.get_num_clauses()));
+        }
+
+        assert_eq!(sizes[1], sizes[sizes.len() - 1]);
+        assert!(matches!(solver.check_assuming(&[parser.parse_formula(queries[2]).unwrap()]), SatResult::Unsat));
+    }
+
+    #[test]
+    fn validity_and_entailment() {
+        let (language, formula) = parse(r"a = b /\ b = c -> f(a) = f(c)");
+        assert!(matches!(QFEUFSolver::valid(&language, &formula), ValidityResult::Valid(premises) if premises.is_empty()));
+
+        let (language, formula) = parse(r"a = b \/ f(a) = f(b)");
+        match QFEUFSolver::valid(&language, &formula) {
+            ValidityResult::Invalid(model) => assert_eq!(model.eval_formula(&formula, &Assignment::new()), Some(false)),
+            _ => panic!("expected invalid"),
+        }
+
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+        let premises = [r"a = b", r"c = d", r"f(b) = g(c)", r"b = c", r"d = e"].iter()
+            .map(|premise| parser.parse_formula(premise).unwrap())
+            .collect::<Vec<_>>();
+        let conclusion = parser.parse_formula(r"f(a) = g(b)").unwrap();
+        let language = parser.get_language();
+
+        match QFEUFSolver::entails(&language, &premises, &conclusion) {
+            ValidityResult::Valid(used) => {
+                assert_eq!(used.len(), 3);
+                for premise in [&premises[0], &premises[2], &premises[3]] {
+                    assert!(used.iter().any(|used| Rc::ptr_eq(used, premise)));
+                }
+            },
+            _ => panic!("expected valid"),
+        }
+
+        match QFEUFSolver::entails(&language, &premises[..3], &conclusion) {
+            ValidityResult::Invalid(model) => {
+                for premise in &premises[..3] {
+                    assert_eq!(model.eval_formula(premise, &Assignment::new()), Some(true));
+                }
+                assert_eq!(model.eval_formula(&conclusion, &Assignment::new()), Some(false));
+            },
+            _ => panic!("expected invalid"),
+        }
+    }
+
+    fn get_core(input: &str, minimize: bool) -> Vec<String> {
+        let (language, formula) = parse(input);
+        match QFEUFSolver::unsat_core(&language, &formula, minimize) {
+            UnsatCoreResult::Unsat(core) => core.iter().map(|conjunct| conjunct.to_string()).collect(),
+            result => panic!("{} is {}", input, result),
+        }
+    }
+
+    #[test]
+    fn unsat_core() {
+        let input = r"a = b /\ c = d /\ f(a) != f(b) /\ b = c /\ f(c) = e /\ (d = e \/ a != d)";
+        let core = get_core(input, false);
+        assert!(core.contains(&"a() = b()".to_string()));
+        assert!(core.contains(&"¬(f(a()) = f(b()))".to_string()));
+
+        // a = b /\ f(a) != f(b) is the only minimal core
+        assert_eq!(get_core(input, true), vec!["a() = b()", "¬(f(a()) = f(b()))"]);
+
+        // nested conjunctions are split as well
+        assert_eq!(get_core(r"(a = b /\ c = d) /\ (b = c /\ e = e) /\ a != d", true), vec!["a() = b()", "c() = d()", "b() = c()", "¬(a() = d())"]);
+
+        let (language, formula) = parse(r"a = b /\ (b = c \/ f(a) = c) /\ f(a) != f(b)");
+        assert!(matches!(QFEUFSolver::unsat_core(&language, &formula, true), UnsatCoreResult::Unsat(_)));
+        let (language, formula) = parse(r"a = b /\ (b = c \/ f(a) = c) /\ f(a) != c");
+        assert!(matches!(QFEUFSolver::unsat_core(&language, &formula, true), UnsatCoreResult::Sat(_)));
+    }
+
+    #[test]
+    fn quantified_formulas_are_unknown() {
+        let sort_a = Sort::new("A");
+        let mut parser = UnsortedParser::new(&sort_a);
+        let quantified = parser.parse_formula("forall x. x = a").unwrap();
+        let ground = parser.parse_formula("a = b").unwrap();
+        let language = parser.get_language();
+        assert!(matches!(QFEUFSolver::sat(&language, &quantified), SatResult::Unknown));
+        assert!(matches!(QFEUFSolver::sat_dnf(&language, &quantified), SatResult::Unknown));
+        assert!(matches!(QFEUFSolver::valid(&language, &quantified), ValidityResult::Unknown));
+        assert!(matches!(QFEUFSolver::entails(&language, std::slice::from_ref(&quantified), &ground), ValidityResult::Unknown));
+        assert!(matches!(QFEUFSolver::unsat_core(&language, &quantified, true), UnsatCoreResult::Unknown));
+
+        // the search answers again once the scope of the quantified formula is closed
+        let mut solver = IncrementalSolver::new(&language);
+        solver.assert(&ground).unwrap();
+        solver.push();
+        solver.assert(&quantified).unwrap();
+        assert!(matches!(solver.check(), SatResult::Unknown));
+        solver.pop(1);
+        assert!(matches!(solver.check(), SatResult::Sat(_)));
+        assert!(matches!(solver.check_assuming(&[quantified]), SatResult::Unknown));
+    }
+}
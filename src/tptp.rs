@@ -0,0 +1,768 @@
+//! Reader for problems in the FOF and CNF languages of TPTP
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::fmt;
+
+use crate::fol::*;
+use crate::parser::{format_diagnostic, get_line_column};
+use crate::solver::*;
+
+/// An error in a TPTP problem at a byte offset of the input,
+/// with the corresponding line and column (both starting from 1)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TptpError {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// A formula of a problem with its name and role (axiom, conjecture, ...)
+#[derive(Debug)]
+pub struct AnnotatedFormula {
+    pub name: String,
+    pub role: String,
+    pub formula: Rc<Formula>,
+}
+
+/// A TPTP problem, with the language inferred from the symbols used in it.
+/// All terms have the single sort `$i`, and the variables of a CNF clause
+/// are universally quantified in the order of their first occurrence
+#[derive(Debug)]
+pub struct TptpProblem {
+    pub language: Rc<Language>,
+    pub formulas: Vec<AnnotatedFormula>,
+}
+
+/// Outcome of solving a problem, named after the SZS ontology
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SzsStatus {
+    /// The axioms entail the conjecture
+    Theorem,
+    /// The axioms and the negation of the conjecture are satisfiable
+    CounterSatisfiable,
+    /// The formulas are unsatisfiable (no conjecture)
+    Unsatisfiable,
+    /// The formulas are satisfiable (no conjecture)
+    Satisfiable,
+    /// The problem has quantifiers, which are outside the decidable
+    /// quantifier-free fragment, and instantiation is disabled
+    Inappropriate,
+    /// The ground instances are satisfiable, but they do not cover the Herbrand universe
+    GaveUp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    LowerWord(String), // also single quoted names, without the quotes
+    UpperWord(String),
+    DollarWord(String),
+    Number(String),
+    DistinctObject(String),
+    Punctuation(&'static str),
+}
+
+#[derive(Debug)]
+struct Token {
+    offset: usize,
+    kind: TokenKind,
+}
+
+/// Punctuations and connectives, where longer ones come first
+const PUNCTUATIONS: &[&str] = &[
+    "<=>", "<~>", "=>", "<=", "!=", "~|", "~&",
+    "(", ")", "[", "]", ",", ".", ":", "!", "?", "~", "&", "|", "=",
+];
+
+struct TptpParser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    sort: Rc<Sort>,
+    function_symbols: Vec<Rc<FunctionSymbol>>,
+    relation_symbols: Vec<Rc<RelationSymbol>>,
+    function_index: HashMap<(String, usize), Rc<FunctionSymbol>>,
+    relation_index: HashMap<(String, usize), Rc<RelationSymbol>>,
+    bound_variables: HashMap<String, Vec<Rc<Variable>>>,
+    implicit_variables: Option<Vec<Rc<Variable>>>, // variables of the current clause, in CNF
+    num_variables: usize,
+}
+
+impl TptpError {
+    fn new(source: &str, offset: usize, message: String) -> TptpError {
+        let (line, column) = get_line_column(source, offset);
+        TptpError { offset, line, column, message }
+    }
+
+    /// Formats the error as the offending line of the source
+    /// followed by a caret under the position and the message
+    pub fn diagnostic(&self, source: &str) -> String {
+        format_diagnostic(source, self.line, self.column, &self.message)
+    }
+}
+
+impl fmt::Display for TptpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::LowerWord(word) | TokenKind::UpperWord(word) | TokenKind::Number(word) => write!(f, "{}", word),
+            TokenKind::DollarWord(word) => write!(f, "${}", word),
+            TokenKind::DistinctObject(object) => write!(f, "\"{}\"", object),
+            TokenKind::Punctuation(punctuation) => write!(f, "{}", punctuation),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Split the input into tokens, skipping whitespaces and comments
+fn tokenize(input: &str) -> Result<Vec<Token>, TptpError> {
+    let mut tokens = vec![];
+    let mut offset = 0;
+
+    loop {
+        let rest = &input[offset..];
+        let trimmed = rest.trim_start();
+        offset += rest.len() - trimmed.len();
+        let rest = trimmed;
+
+        let first = match rest.chars().next() {
+            Some(first) => first,
+            None => return Ok(tokens),
+        };
+
+        let (length, kind) = if first == '%' {
+            (rest.find('\n').unwrap_or(rest.len()), None)
+        } else if rest.starts_with("/*") {
+            match rest.find("*/") {
+                Some(end) => (end + 2, None),
+                None => return Err(TptpError::new(input, offset, "unterminated comment".to_string())),
+            }
+        } else if first == '\'' || first == '"' {
+            // quoted names and distinct objects, where \ escapes the next character
+            let mut value = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            let end = loop {
+                match chars.next() {
+                    None => return Err(TptpError::new(input, offset, format!("unterminated {}", first))),
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => value.push(c),
+                        None => return Err(TptpError::new(input, offset, format!("unterminated {}", first))),
+                    },
+                    Some((i, c)) if c == first => break i + 1,
+                    Some((_, c)) => value.push(c),
+                }
+            };
+            let kind = if first == '\'' { TokenKind::LowerWord(value) } else { TokenKind::DistinctObject(value) };
+            (end, Some(kind))
+        } else if first.is_ascii_alphanumeric() || first == '$' {
+            let end = rest[1..].find(|c| !is_word_char(c)).map(|end| end + 1).unwrap_or(rest.len());
+            let word = rest[..end].to_string();
+            let kind = if first == '$' {
+                TokenKind::DollarWord(word[1..].to_string())
+            } else if first.is_ascii_digit() {
+                TokenKind::Number(word)
+            } else if first.is_ascii_uppercase() {
+                TokenKind::UpperWord(word)
+            } else {
+                TokenKind::LowerWord(word)
+            };
+            (end, Some(kind))
+        } else {
+            match PUNCTUATIONS.iter().find(|punctuation| rest.starts_with(**punctuation)) {
+                Some(punctuation) => (punctuation.len(), Some(TokenKind::Punctuation(punctuation))),
+                None => return Err(TptpError::new(input, offset, format!("unexpected character {}", first))),
+            }
+        };
+
+        if let Some(kind) = kind {
+            tokens.push(Token { offset, kind });
+        }
+        offset += length;
+    }
+}
+
+impl<'a> TptpParser<'a> {
+    fn new(input: &'a str) -> Result<TptpParser<'a>, TptpError> {
+        Ok(TptpParser {
+            input,
+            tokens: tokenize(input)?,
+            position: 0,
+            sort: Sort::new("$i"),
+            function_symbols: vec![],
+            relation_symbols: vec![],
+            function_index: HashMap::new(),
+            relation_index: HashMap::new(),
+            bound_variables: HashMap::new(),
+            implicit_variables: None,
+            num_variables: 0,
+        })
+    }
+
+    fn get_offset(&self) -> usize {
+        self.tokens.get(self.position).map(|token| token.offset).unwrap_or(self.input.len())
+    }
+
+    fn error<T>(&self, offset: usize, message: impl Into<String>) -> Result<T, TptpError> {
+        Err(TptpError::new(self.input, offset, message.into()))
+    }
+
+    /// Report that something else was expected at the current token
+    fn expected<T>(&self, expected: &str) -> Result<T, TptpError> {
+        match self.peek() {
+            Some(token) => self.error(self.get_offset(), format!("expected {}, found {}", expected, token)),
+            None => self.error(self.get_offset(), format!("expected {}, found end of input", expected)),
+        }
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn next(&mut self) -> Option<TokenKind> {
+        let token = self.tokens.get(self.position).map(|token| token.kind.clone());
+        self.position += 1;
+        token
+    }
+
+    fn is_punctuation(&self, punctuation: &str) -> bool {
+        matches!(self.peek(), Some(TokenKind::Punctuation(other)) if *other == punctuation)
+    }
+
+    fn expect_punctuation(&mut self, punctuation: &str) -> Result<(), TptpError> {
+        if !self.is_punctuation(punctuation) {
+            return self.expected(&format!("`{}`", punctuation));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn function_symbol(&mut self, name: &str, arity: usize) -> Rc<FunctionSymbol> {
+        let key = (name.to_string(), arity);
+        if let Some(symbol) = self.function_index.get(&key) {
+            return symbol.clone();
+        }
+        let symbol = FunctionSymbol::new(name, &vec![&self.sort; arity], &self.sort);
+        self.function_symbols.push(symbol.clone());
+        self.function_index.insert(key, symbol.clone());
+        symbol
+    }
+
+    fn relation_symbol(&mut self, name: &str, arity: usize) -> Rc<RelationSymbol> {
+        let key = (name.to_string(), arity);
+        if let Some(symbol) = self.relation_index.get(&key) {
+            return symbol.clone();
+        }
+        let symbol = RelationSymbol::new(name, &vec![&self.sort; arity]);
+        self.relation_symbols.push(symbol.clone());
+        self.relation_index.insert(key, symbol.clone());
+        symbol
+    }
+
+    fn new_variable(&mut self) -> Rc<Variable> {
+        let variable = Rc::new(Variable { index: self.num_variables, sort: self.sort.clone() });
+        self.num_variables += 1;
+        variable
+    }
+
+    fn variable(&mut self, name: &str, offset: usize) -> Result<Rc<Term>, TptpError> {
+        if let Some(variable) = self.bound_variables.get(name).and_then(|variables| variables.last()) {
+            return Ok(Rc::new(Term::Variable(variable.clone())));
+        }
+
+        // variables of a clause are bound at their first occurrence
+        if self.implicit_variables.is_none() {
+            return self.error(offset, format!("variable {} is not bound", name));
+        }
+        let variable = self.new_variable();
+        self.bound_variables.insert(name.to_string(), vec![variable.clone()]);
+        self.implicit_variables.as_mut().unwrap().push(variable.clone());
+        Ok(Rc::new(Term::Variable(variable)))
+    }
+
+    /// Parses the optional parenthesized arguments of a symbol
+    fn arguments(&mut self) -> Result<Vec<Rc<Term>>, TptpError> {
+        let mut arguments = vec![];
+        if self.is_punctuation("(") {
+            self.position += 1;
+            loop {
+                arguments.push(self.term()?);
+                if !self.is_punctuation(",") {
+                    break;
+                }
+                self.position += 1;
+            }
+            self.expect_punctuation(")")?;
+        }
+        Ok(arguments)
+    }
+
+    fn term(&mut self) -> Result<Rc<Term>, TptpError> {
+        let offset = self.get_offset();
+        match self.peek().cloned() {
+            Some(TokenKind::UpperWord(name)) => {
+                self.position += 1;
+                self.variable(&name, offset)
+            },
+            Some(TokenKind::LowerWord(name)) => {
+                self.position += 1;
+                let arguments = self.arguments()?;
+                let symbol = self.function_symbol(&name, arguments.len());
+                Ok(Term::new_application(&symbol, &arguments.iter().collect::<Vec<_>>()))
+            },
+            Some(token @ (TokenKind::Number(_) | TokenKind::DistinctObject(_) | TokenKind::DollarWord(_))) =>
+                self.error(offset, format!("{} is not supported in FOF", token)),
+            _ => self.expected("a term"),
+        }
+    }
+
+    /// Parses the right-hand side of an equality or a disequality, if any
+    fn equality(&mut self, left: &Rc<Term>) -> Result<Option<Rc<Formula>>, TptpError> {
+        for (operator, negated) in [("=", false), ("!=", true)] {
+            if self.is_punctuation(operator) {
+                self.position += 1;
+                let equality = Formula::new_equality(left, &self.term()?);
+                return Ok(Some(if negated { Formula::new_negation(&equality) } else { equality }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn atomic_formula(&mut self) -> Result<Rc<Formula>, TptpError> {
+        match self.peek().cloned() {
+            Some(TokenKind::DollarWord(word)) if word == "true" || word == "false" => {
+                self.position += 1;
+                Ok(if word == "true" { Formula::verum() } else { Formula::falsum() })
+            },
+            // an application is a predicate unless it is followed by = or !=
+            Some(TokenKind::LowerWord(name)) => {
+                self.position += 1;
+                let arguments = self.arguments()?;
+                let arguments = arguments.iter().collect::<Vec<_>>();
+                if self.is_punctuation("=") || self.is_punctuation("!=") {
+                    let symbol = self.function_symbol(&name, arguments.len());
+                    let term = Term::new_application(&symbol, &arguments);
+                    Ok(self.equality(&term)?.unwrap())
+                } else {
+                    let symbol = self.relation_symbol(&name, arguments.len());
+                    Ok(Formula::new_relation_application(&symbol, &arguments))
+                }
+            },
+            _ => {
+                let term = self.term()?;
+                match self.equality(&term)? {
+                    Some(equality) => Ok(equality),
+                    None => self.expected("`=` or `!=`"),
+                }
+            },
+        }
+    }
+
+    /// Parses a negation, a quantified formula, a parenthesized formula or an atomic formula
+    fn unit_formula(&mut self) -> Result<Rc<Formula>, TptpError> {
+        if self.is_punctuation("~") {
+            self.position += 1;
+            return Ok(Formula::new_negation(&self.unit_formula()?));
+        }
+
+        if self.is_punctuation("(") {
+            self.position += 1;
+            let formula = self.formula()?;
+            self.expect_punctuation(")")?;
+            return Ok(formula);
+        }
+
+        let universal = self.is_punctuation("!");
+        if !universal && !self.is_punctuation("?") {
+            return self.atomic_formula();
+        }
+
+        self.position += 1;
+        self.expect_punctuation("[")?;
+        let mut names = vec![];
+        loop {
+            match self.next() {
+                Some(TokenKind::UpperWord(name)) => names.push(name),
+                _ => {
+                    self.position -= 1;
+                    return self.expected("a variable");
+                },
+            }
+            if !self.is_punctuation(",") {
+                break;
+            }
+            self.position += 1;
+        }
+        self.expect_punctuation("]")?;
+        self.expect_punctuation(":")?;
+
+        let mut variables = vec![];
+        for name in &names {
+            let variable = self.new_variable();
+            self.bound_variables.entry(name.clone()).or_default().push(variable.clone());
+            variables.push(variable);
+        }
+        let body = self.unit_formula();
+        for name in &names {
+            self.bound_variables.get_mut(name).unwrap().pop();
+        }
+
+        Ok(variables.into_iter().rev().fold(body?, |body, variable| Rc::new(if universal {
+            Formula::UniversalQuantification(variable, body)
+        } else {
+            Formula::ExistentialQuantification(variable, body)
+        })))
+    }
+
+    /// Parses a formula, where the binary connectives other than & and |
+    /// take exactly two unit formulas, and & and | cannot be mixed without parentheses
+    fn formula(&mut self) -> Result<Rc<Formula>, TptpError> {
+        let left = self.unit_formula()?;
+
+        for operator in ["&", "|"] {
+            if self.is_punctuation(operator) {
+                let mut formulas = vec![left];
+                while self.is_punctuation(operator) {
+                    self.position += 1;
+                    formulas.push(self.unit_formula()?);
+                }
+                let formulas = formulas.iter().collect::<Vec<_>>();
+                return Ok(if operator == "&" { Formula::new_conjunction(&formulas) } else { Formula::new_disjunction(&formulas) });
+            }
+        }
+
+        let operator = match self.peek() {
+            Some(TokenKind::Punctuation(operator)) if ["<=>", "<~>", "=>", "<=", "~|", "~&"].contains(operator) => *operator,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.unit_formula()?;
+
+        Ok(match operator {
+            "<=>" => Formula::new_equivalence(&left, &right),
+            "<~>" => Formula::new_negation(&Formula::new_equivalence(&left, &right)),
+            "=>" => Formula::new_implication(&left, &right),
+            "<=" => Formula::new_implication(&right, &left),
+            "~|" => Formula::new_negation(&Formula::new_disjunction(&[&left, &right])),
+            _ => Formula::new_negation(&Formula::new_conjunction(&[&left, &right])),
+        })
+    }
+
+    /// Skips the source and useful info annotations of an annotated formula
+    fn skip_annotations(&mut self) -> Result<(), TptpError> {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => return self.expected("`)`"),
+                Some(TokenKind::Punctuation("(" | "[")) => depth += 1,
+                Some(TokenKind::Punctuation(")" | "]")) if depth == 0 => return Ok(()),
+                Some(TokenKind::Punctuation(")" | "]")) => depth -= 1,
+                _ => {},
+            }
+            self.position += 1;
+        }
+    }
+
+    fn annotated_formula(&mut self) -> Result<AnnotatedFormula, TptpError> {
+        let offset = self.get_offset();
+        let language = match self.next() {
+            Some(TokenKind::LowerWord(language)) if language == "fof" || language == "cnf" => language,
+            Some(TokenKind::LowerWord(word)) if word == "include" => return self.error(offset, "include directives are not supported"),
+            Some(TokenKind::LowerWord(language)) => return self.error(offset, format!("{} formulas are not supported", language)),
+            _ => {
+                self.position -= 1;
+                return self.expected("fof or cnf");
+            },
+        };
+
+        self.expect_punctuation("(")?;
+        let name = match self.next() {
+            Some(TokenKind::LowerWord(name) | TokenKind::Number(name)) => name,
+            _ => {
+                self.position -= 1;
+                return self.expected("a name");
+            },
+        };
+        self.expect_punctuation(",")?;
+        let role = match self.next() {
+            Some(TokenKind::LowerWord(role)) => role,
+            _ => {
+                self.position -= 1;
+                return self.expected("a role");
+            },
+        };
+        self.expect_punctuation(",")?;
+
+        self.bound_variables.clear();
+        self.implicit_variables = if language == "cnf" { Some(vec![]) } else { None };
+        let formula = self.formula()?;
+        let formula = self.implicit_variables.take().unwrap_or_default().into_iter().rev()
+            .fold(formula, |body, variable| Rc::new(Formula::UniversalQuantification(variable, body)));
+
+        if self.is_punctuation(",") {
+            self.position += 1;
+            self.skip_annotations()?;
+        }
+        self.expect_punctuation(")")?;
+        self.expect_punctuation(".")?;
+
+        Ok(AnnotatedFormula { name, role, formula })
+    }
+}
+
+/// Call the function on every tuple of the given length with elements in 0..size
+fn for_each_tuple(length: usize, size: usize, mut f: impl FnMut(&[usize])) {
+    if length > 0 && size == 0 {
+        return;
+    }
+    let mut tuple = vec![0; length];
+    loop {
+        f(&tuple);
+        // advance the tuple like an odometer
+        let mut i = 0;
+        while i < length && tuple[i] + 1 == size {
+            tuple[i] = 0;
+            i += 1;
+        }
+        if i == length {
+            return;
+        }
+        tuple[i] += 1;
+    }
+}
+
+/// Skolemize the formulas and instantiate their universal quantifiers with all ground terms
+/// of depth at most the given depth. Returns the language with the Skolem functions,
+/// the ground instances, and whether the ground terms are the whole Herbrand universe
+fn instantiate(language: &Rc<Language>, formulas: &[Rc<Formula>], depth: usize) -> (Rc<Language>, Vec<Rc<Formula>>, bool) {
+    let mut language = language.clone();
+    let mut matrices = vec![];
+    for formula in formulas {
+        let (skolemized, skolem_language) = Formula::skolemize(formula, &language);
+        language = skolem_language;
+
+        // a Skolemized formula is prenex with only universal quantifiers
+        let mut variables = vec![];
+        let mut matrix = skolemized;
+        while let Formula::UniversalQuantification(variable, body) = matrix.as_ref() {
+            variables.push(variable.clone());
+            let body = body.clone();
+            matrix = body;
+        }
+        matrices.push((variables, matrix));
+    }
+
+    let sort = language.iter_sorts().next().unwrap().clone();
+    let mut constants = language.iter_function_symbols()
+        .filter(|symbol| symbol.arity() == 0)
+        .map(|symbol| Term::new_application(symbol, &[]))
+        .collect::<Vec<_>>();
+
+    // the Herbrand universe has at least one constant
+    if constants.is_empty() {
+        let name = (0..).map(|i| format!("c{}", i))
+            .find(|name| language.iter_function_symbols().all(|symbol| symbol.get_name() != name))
+            .unwrap();
+        let constant = FunctionSymbol::new(&name, &[], &sort);
+        constants.push(Term::new_application(&constant, &[]));
+
+        let sorts = language.iter_sorts().collect::<Vec<_>>();
+        let mut functions = language.iter_function_symbols().collect::<Vec<_>>();
+        functions.push(&constant);
+        let relations = language.iter_relation_symbols().collect::<Vec<_>>();
+        language = Language::new(&sorts, &functions, &relations);
+    }
+
+    // each level applies the functions to tuples with at least one term of the previous level
+    let mut universe = constants;
+    let mut previous_level = 0;
+    for _ in 0..depth {
+        let level = universe.len();
+        for symbol in language.iter_function_symbols().filter(|symbol| symbol.arity() > 0) {
+            for_each_tuple(symbol.arity(), level, |tuple| {
+                if tuple.iter().any(|i| *i >= previous_level) {
+                    let arguments = tuple.iter().map(|i| &universe[*i]).collect::<Vec<_>>();
+                    let term = Term::new_application(symbol, &arguments);
+                    universe.push(term);
+                }
+            });
+        }
+        previous_level = level;
+    }
+
+    let mut instances = vec![];
+    for (variables, matrix) in &matrices {
+        for_each_tuple(variables.len(), universe.len(), |tuple| {
+            let substitution = variables.iter().cloned()
+                .zip(tuple.iter().map(|i| universe[*i].clone()))
+                .collect::<Substitution>();
+            instances.push(Formula::substitute(matrix, &substitution));
+        });
+    }
+
+    let complete = language.iter_function_symbols().all(|symbol| symbol.arity() == 0);
+    (language, instances, complete)
+}
+
+impl TptpProblem {
+    /// Parse the FOF and CNF formulas of a problem
+    pub fn parse(input: &str) -> Result<TptpProblem, TptpError> {
+        let mut parser = TptpParser::new(input)?;
+        let mut formulas = vec![];
+        while parser.peek().is_some() {
+            formulas.push(parser.annotated_formula()?);
+        }
+
+        let language = Language::new(
+            &[&parser.sort],
+            &parser.function_symbols.iter().collect::<Vec<_>>(),
+            &parser.relation_symbols.iter().collect::<Vec<_>>(),
+        );
+        Ok(TptpProblem { language, formulas })
+    }
+
+    fn has_conjecture(&self) -> bool {
+        self.formulas.iter().any(|formula| formula.role == "conjecture")
+    }
+
+    /// Get the formulas whose unsatisfiability solves the problem:
+    /// all formulas other than conjectures, and the negated conjunction of the conjectures
+    pub fn get_refutation_formulas(&self) -> Vec<Rc<Formula>> {
+        let mut formulas = self.formulas.iter()
+            .filter(|formula| formula.role != "conjecture")
+            .map(|formula| formula.formula.clone())
+            .collect::<Vec<_>>();
+
+        let conjectures = self.formulas.iter()
+            .filter(|formula| formula.role == "conjecture")
+            .map(|formula| &formula.formula)
+            .collect::<Vec<_>>();
+        match conjectures.len() {
+            0 => {},
+            1 => formulas.push(Formula::new_negation(conjectures[0])),
+            _ => formulas.push(Formula::new_negation(&Formula::new_conjunction(&conjectures))),
+        }
+        formulas
+    }
+
+    /// Solve a problem. Ground problems are decided by QFEUFSolver. Quantified problems
+    /// are Inappropriate, unless an instantiation depth is given, in which case the
+    /// Skolemized formulas are instantiated with the ground terms up to that depth
+    pub fn solve(&self, instantiation_depth: Option<usize>) -> SzsStatus {
+        let formulas = self.get_refutation_formulas();
+
+        let (language, formulas, complete) = if formulas.iter().any(|formula| formula.has_quantifiers()) {
+            match instantiation_depth {
+                Some(depth) => instantiate(&self.language, &formulas, depth),
+                None => return SzsStatus::Inappropriate,
+            }
+        } else {
+            (self.language.clone(), formulas, true)
+        };
+
+        let formula = Formula::new_conjunction(&formulas.iter().collect::<Vec<_>>());
+        match QFEUFSolver::sat(&language, &formula) {
+            SatResult::Unsat if self.has_conjecture() => SzsStatus::Theorem,
+            SatResult::Unsat => SzsStatus::Unsatisfiable,
+            SatResult::Sat(_) if complete && self.has_conjecture() => SzsStatus::CounterSatisfiable,
+            SatResult::Sat(_) if complete => SzsStatus::Satisfiable,
+            _ => SzsStatus::GaveUp,
+        }
+    }
+}
+
+impl fmt::Display for SzsStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(input: &str, instantiation_depth: Option<usize>) -> SzsStatus {
+        let problem = TptpProblem::parse(input).unwrap_or_else(|error| panic!("failed to parse:\n{}", error.diagnostic(input)));
+        problem.solve(instantiation_depth)
+    }
+
+    #[test]
+    fn ground_problems() {
+        let problem = r"
+            % f(f(f(a))) = a and f(f(f(f(f(a))))) = a imply f(a) = a
+            fof(ax1, axiom, f(f(f(a))) = a).
+            fof(ax2, axiom, f(f(f(f(f(a))))) = a, file('ax', ax2)).
+            fof('the conjecture', conjecture, f(a) = a /* block comment */).
+        ";
+        assert_eq!(solve(problem, None), SzsStatus::Theorem);
+
+        let problem = r"
+            fof(ax1, axiom, (p(a) <=> ~ q) & (a = b | a != c)).
+            fof(ax2, axiom, p(b) <= (q ~| $false)).
+            fof(c, conjecture, p(c) => a = b).
+        ";
+        assert_eq!(solve(problem, None), SzsStatus::CounterSatisfiable);
+
+        let problem = r"
+            cnf(c1, negated_conjecture, p(a) | ~ q(f(a))).
+            cnf(c2, plain, (~ p(a))).
+            cnf(c3, plain, q(f(b))).
+            cnf(c4, plain, a = b).
+        ";
+        assert_eq!(solve(problem, None), SzsStatus::Unsatisfiable);
+    }
+
+    #[test]
+    fn quantified_problems() {
+        let problem = r"
+            cnf(c1, axiom, ~ p(X) | p(f(X))).
+            cnf(c2, axiom, p(a)).
+            cnf(c3, negated_conjecture, ~ p(f(f(a)))).
+        ";
+        let parsed = TptpProblem::parse(problem).unwrap();
+        assert_eq!(parsed.formulas[0].formula.to_string(), "∀x0:$i ((¬(p(x0:$i)) ∨ p(f(x0:$i))))");
+        assert_eq!(solve(problem, None), SzsStatus::Inappropriate);
+        assert_eq!(solve(problem, Some(0)), SzsStatus::GaveUp);
+        assert_eq!(solve(problem, Some(1)), SzsStatus::Unsatisfiable);
+
+        // without function symbols, the instances cover the Herbrand universe
+        let problem = r"
+            fof(ax1, axiom, ! [X, Y] : (r(X, Y) => r(Y, X))).
+            fof(ax2, axiom, ? [X] : r(a, X)).
+            fof(c, conjecture, ! [X] : r(X, X)).
+        ";
+        assert_eq!(solve(problem, Some(0)), SzsStatus::CounterSatisfiable);
+
+        let problem = r"
+            fof(ax1, axiom, ! [X, Y] : (r(X, Y) => r(Y, X))).
+            fof(ax2, axiom, ? [X] : r(a, X)).
+            fof(c1, conjecture, ? [X] : r(X, a)).
+        ";
+        assert_eq!(solve(problem, Some(0)), SzsStatus::Theorem);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = TptpProblem::parse("fof(a, axiom, p(X)).").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 17));
+        assert_eq!(error.message, "variable X is not bound");
+
+        let error = TptpProblem::parse("fof(a, axiom, p & q | r).").unwrap_err();
+        assert_eq!(error.message, "expected `)`, found |");
+
+        let error = TptpProblem::parse("fof(a, axiom, p).\ninclude('Axioms/SET001.ax').").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(error.message, "include directives are not supported");
+
+        let error = TptpProblem::parse("cnf(a, axiom, p(1)).").unwrap_err();
+        assert_eq!(error.message, "1 is not supported in FOF");
+    }
+}